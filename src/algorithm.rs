@@ -1,9 +1,19 @@
+use std::fmt;
+
+use crate::metric::Metric;
 use crate::permutation::Permutation;
 
+#[derive(Clone, Debug)]
 pub struct Algorithm {
     moves: Vec<Move>,
 }
 
+impl Default for Algorithm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Algorithm {
     pub fn new() -> Algorithm {
         Algorithm {
@@ -11,6 +21,15 @@ impl Algorithm {
         }
     }
 
+    pub fn from_moves(moves: &[Move]) -> Algorithm {
+        Algorithm {
+            moves: Vec::from(moves)
+        }
+    }
+
+    // Parsing and printing of algorithms and moves are inherent methods, which all the
+    // callers of the original API use
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(algorithm: &str) -> Result<Algorithm, &'static str> {
         let mut moves: Vec<Move> = vec![];
 
@@ -41,6 +60,10 @@ impl Algorithm {
         self
     }
 
+    pub fn moves(&self) -> &[Move] {
+        &self.moves
+    }
+
     pub fn last(&self) -> Option<Move> {
         self.moves.last().copied()
     }
@@ -55,6 +78,41 @@ impl Algorithm {
         res
     }
 
+    pub fn len(&self) -> usize {
+        self.moves.len()
+    }
+
+    // Length as counted in `metric`, where len() counts every move as one
+    pub fn length(&self, metric: Metric) -> usize {
+        (0..self.moves.len())
+            .map(|i| metric.cost(&self.moves[..i], self.moves[i]))
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.moves.is_empty()
+    }
+
+    // The same algorithm performed with the cube rotated by `rotation`
+    pub fn rotate(&self, rotation: Permutation) -> Algorithm {
+        Algorithm {
+            moves: self.moves
+                .iter()
+                .map(|mov| mov.rotate(rotation))
+                .collect()
+        }
+    }
+
+    // The algorithm seen in a mirror between the L and R faces
+    pub fn mirror(&self) -> Algorithm {
+        Algorithm {
+            moves: self.moves
+                .iter()
+                .map(|mov| mov.mirror())
+                .collect()
+        }
+    }
+
     pub fn inverse(&self) -> Algorithm {
         Algorithm {
             moves: self.moves
@@ -65,6 +123,31 @@ impl Algorithm {
         }
     }
 
+    // Merges turns of the same face that end up next to each other, also across turns
+    // of the opposite face since those commute, and drops the ones that add up to nothing
+    pub fn cancel(&self) -> Algorithm {
+        let mut moves: Vec<Move> = vec![];
+
+        for &mov in &self.moves {
+            let run = moves.iter().rev().take_while(|other| other.axis() == mov.axis()).count();
+            let start = moves.len() - run;
+
+            let Some(i) = moves[start..].iter().position(|other| other.is_similar(mov)) else {
+                moves.push(mov);
+                continue;
+            };
+
+            match (moves[start + i].1 + mov.1).rem_euclid(4) {
+                0 => { moves.remove(start + i); },
+                3 => moves[start + i].1 = -1,
+                amount => moves[start + i].1 = amount
+            }
+        }
+
+        Algorithm { moves }
+    }
+
+    #[allow(clippy::inherent_to_string)]
     pub fn to_string(&self) -> String {
         self.moves
             .iter()
@@ -74,7 +157,7 @@ impl Algorithm {
     }
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
 pub struct Move(MoveBase, isize);
 
 impl Move {
@@ -87,6 +170,21 @@ impl Move {
         Move(MoveBase::B, 1), Move(MoveBase::B, 2), Move(MoveBase::B, -1),
     ];
 
+    // Slice moves and rotations move the centers, which the coordinates and pruning
+    // tables don't know about. They can only be searched on whole permutations.
+    pub const SLICE_MOVES: [Move; 9] = [
+        Move(MoveBase::M, 1), Move(MoveBase::M, 2), Move(MoveBase::M, -1),
+        Move(MoveBase::E, 1), Move(MoveBase::E, 2), Move(MoveBase::E, -1),
+        Move(MoveBase::S, 1), Move(MoveBase::S, 2), Move(MoveBase::S, -1),
+    ];
+
+    pub const ROTATIONS: [Move; 9] = [
+        Move(MoveBase::X, 1), Move(MoveBase::X, 2), Move(MoveBase::X, -1),
+        Move(MoveBase::Y, 1), Move(MoveBase::Y, 2), Move(MoveBase::Y, -1),
+        Move(MoveBase::Z, 1), Move(MoveBase::Z, 2), Move(MoveBase::Z, -1),
+    ];
+
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(name: &str) -> Result<Move, &'static str> {
         let mut modifier = 1;
 
@@ -103,6 +201,18 @@ impl Move {
         Ok(Move(MoveBase::from_str(name)?, modifier))
     }
 
+    // Position in MOVES_LIST, with slice moves numbered after it in SLICE_MOVES order
+    pub fn index(self) -> usize {
+        debug_assert!(!self.is_rotation(), "Rotations have no index");
+
+        self.0 as usize * 3 + match self.1 {
+            1 => 0,
+            2 => 1,
+            -1 => 2,
+            _ => panic!("Unrecognized move modifier")
+        }
+    }
+
     pub fn inverse(self) -> Move {
         Move(self.0, if self.1 == 2 { 2 } else { -self.1 })
     }
@@ -111,12 +221,75 @@ impl Move {
         self.0 == other.0
     }
 
+    // Whether a search should try this move right after `previous`. Turning the same
+    // layer twice in a row is never useful, and moves on the same axis commute, so
+    // they are only tried in MoveBase order: U D is searched but D U isn't.
+    pub fn can_follow(self, previous: Move) -> bool {
+        self.axis() != previous.axis() || self.0 > previous.0
+    }
+
     pub fn keeps_eo(self) -> bool {
-        !((self.0 == MoveBase::F || self.0 == MoveBase::B) && self.1 != 2)
+        self.is_face_turn() && !((self.0 == MoveBase::F || self.0 == MoveBase::B) && self.1 != 2)
     }
 
     pub fn keeps_domino(self) -> bool {
-        self.1 == 2 || self.0 == MoveBase::U || self.0 == MoveBase::D
+        self.is_face_turn() && (self.1 == 2 || self.0 == MoveBase::U || self.0 == MoveBase::D)
+    }
+
+    pub fn is_face_turn(self) -> bool {
+        (self.0 as usize) < MoveBase::M as usize
+    }
+
+    pub fn is_slice(self) -> bool {
+        matches!(self.0, MoveBase::M | MoveBase::E | MoveBase::S)
+    }
+
+    pub fn is_rotation(self) -> bool {
+        matches!(self.0, MoveBase::X | MoveBase::Y | MoveBase::Z)
+    }
+
+    // Quarter turns in either direction
+    pub fn amount(self) -> usize {
+        self.1.unsigned_abs()
+    }
+
+    // Two face turns on opposite sides of the same axis that move the pieces like a
+    // slice move, e.g. R L' is M' with the centers kept in place
+    pub fn forms_slice_with(self, other: Move) -> bool {
+        self.is_face_turn() &&
+        other.is_face_turn() &&
+        self.axis() == other.axis() &&
+        !self.is_similar(other) &&
+        (self.1 + other.1).rem_euclid(4) == 0
+    }
+
+    pub fn axis(self) -> Axis {
+        self.0.axis()
+    }
+
+    // The move that has the same effect as this one once the cube is rotated by `rotation`
+    pub fn rotate(self, rotation: Permutation) -> Move {
+        let perm = self.permutation().conjugate(rotation);
+
+        Move::MOVES_LIST
+            .into_iter()
+            .chain(Move::SLICE_MOVES)
+            .chain(Move::ROTATIONS)
+            .find(|mov| mov.permutation() == perm)
+            .unwrap()
+    }
+
+    // Mirroring swaps L and R and turns everything the other way, except for turns
+    // around the L-R axis itself
+    pub fn mirror(self) -> Move {
+        let amount = if self.1 == 2 { 2 } else { -self.1 };
+
+        match self.0 {
+            MoveBase::L => Move(MoveBase::R, amount),
+            MoveBase::R => Move(MoveBase::L, amount),
+            MoveBase::M | MoveBase::X => self,
+            base => Move(base, amount)
+        }
     }
 
     pub fn is_halfturn(self) -> bool {
@@ -127,6 +300,7 @@ impl Move {
         perm + self.permutation()
     }
 
+    #[allow(clippy::inherent_to_string)]
     pub fn to_string(self) -> String {
         self.0.to_string() + match self.1 {
             1 => "",
@@ -141,7 +315,8 @@ impl Move {
     }
 }
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+// Moves on the same axis are searched in the order the variants are declared in
+#[derive(Copy, Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 enum MoveBase {
     U,
     D,
@@ -149,6 +324,12 @@ enum MoveBase {
     R,
     F,
     B,
+    M,
+    E,
+    S,
+    X,
+    Y,
+    Z,
 }
 
 impl MoveBase {
@@ -160,10 +341,25 @@ impl MoveBase {
             "R" => Ok(MoveBase::R),
             "F" => Ok(MoveBase::F),
             "B" => Ok(MoveBase::B),
+            "M" => Ok(MoveBase::M),
+            "E" => Ok(MoveBase::E),
+            "S" => Ok(MoveBase::S),
+            "x" => Ok(MoveBase::X),
+            "y" => Ok(MoveBase::Y),
+            "z" => Ok(MoveBase::Z),
             _ => Err("Could not parse move")
         }
     }
 
+    fn axis(self) -> Axis {
+        match self {
+            MoveBase::U | MoveBase::D | MoveBase::E | MoveBase::Y => Axis::UD,
+            MoveBase::L | MoveBase::R | MoveBase::M | MoveBase::X => Axis::RL,
+            MoveBase::F | MoveBase::B | MoveBase::S | MoveBase::Z => Axis::FB
+        }
+    }
+
+    #[allow(clippy::inherent_to_string)]
     fn to_string(self) -> String {
         String::from(match self {
             MoveBase::U => "U",
@@ -171,7 +367,13 @@ impl MoveBase {
             MoveBase::L => "L",
             MoveBase::R => "R",
             MoveBase::F => "F",
-            MoveBase::B => "B"
+            MoveBase::B => "B",
+            MoveBase::M => "M",
+            MoveBase::E => "E",
+            MoveBase::S => "S",
+            MoveBase::X => "x",
+            MoveBase::Y => "y",
+            MoveBase::Z => "z"
         })
     }
 
@@ -182,14 +384,41 @@ impl MoveBase {
             MoveBase::L => Permutation::L,
             MoveBase::R => Permutation::R,
             MoveBase::F => Permutation::F,
-            MoveBase::B => Permutation::B
+            MoveBase::B => Permutation::B,
+            MoveBase::M => Permutation::M,
+            MoveBase::E => Permutation::E,
+            MoveBase::S => Permutation::S,
+            MoveBase::X => Permutation::X,
+            MoveBase::Y => Permutation::Y,
+            MoveBase::Z => Permutation::Z
         }
     }
 }
 
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub enum Axis {
+    UD,
+    RL,
+    FB,
+}
+
+impl Axis {
+    pub const ALL: [Axis; 3] = [Axis::UD, Axis::RL, Axis::FB];
+}
+
+impl fmt::Display for Axis {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Axis::UD => "ud",
+            Axis::RL => "rl",
+            Axis::FB => "fb"
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::Algorithm;
+    use super::{ Algorithm, Move };
     use super::Permutation;
 
     #[test]
@@ -207,6 +436,31 @@ mod tests {
         assert_ne!(inv.execute(alg.execute(Permutation::ID)), Permutation::ID);
     }
 
+    #[test]
+    fn rotated_moves_follow_the_rotation() {
+        let rotated = Algorithm::from_str("R U' F2 L B' D")
+            .unwrap()
+            .moves()
+            .iter()
+            .map(|mov| mov.rotate(Permutation::Y).to_string())
+            .collect::<Vec<String>>()
+            .join(" ");
+        assert_eq!(rotated, "F U' L2 B R' D");
+    }
+
+    #[test]
+    fn cancels_across_opposite_faces() {
+        let alg = Algorithm::from_str("R U U L R' L' F2 B F2 D U' D'").unwrap();
+        assert_eq!(alg.cancel().to_string(), "R U2 R' B U'");
+        assert_eq!(alg.cancel().execute(Permutation::ID), alg.execute(Permutation::ID));
+    }
+
+    #[test]
+    fn slice_moves_are_indexed_after_face_turns() {
+        let indices: Vec<usize> = Move::MOVES_LIST.into_iter().chain(Move::SLICE_MOVES).map(Move::index).collect();
+        assert_eq!(indices, (0..27).collect::<Vec<_>>());
+    }
+
     #[test]
     fn superflip() {
         let superflip1 = Algorithm::from_str("U R2 F B R B2 R U2 L B2 R U' D' R2 F R' L B2 U2 F2").unwrap();
@@ -0,0 +1,241 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use crate::algorithm::{ Axis, Move };
+use crate::coords::{ self, DrCoord, HtrCoord, MoveTables, HTR_CORNERS_SIZE, SLICE_EDGES };
+use crate::permutation::{ CornerLoc, EdgeLoc, Permutation };
+use crate::pruning::PruningTables;
+use crate::stage::rotation_to;
+
+// How far a state with EO is from DR on some axis, the way FMC solvers describe it:
+// the corners misoriented relative to that axis and the edges of the slice between
+// its two faces that are out of that slice. Written like "4c4e".
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct DrCase {
+    pub corners: usize,
+    pub edges: usize,
+}
+
+impl DrCase {
+    pub const SOLVED: Self = Self { corners: 0, edges: 0 };
+
+    // The case of `perm` for DR on `dr_axis`
+    pub fn of(perm: &Permutation, dr_axis: Axis) -> Self {
+        Self::from_coord(DrCoord::from_perm(&perm.conjugate(rotation_to(&[(dr_axis, Axis::UD)]))))
+    }
+
+    // The case for DR on each axis other than `eo_axis`
+    pub fn by_axis(perm: &Permutation, eo_axis: Axis) -> Vec<(Axis, Self)> {
+        Axis::ALL
+            .into_iter()
+            .filter(|&axis| axis != eo_axis)
+            .map(|axis| (axis, Self::of(perm, axis)))
+            .collect()
+    }
+
+    // The case of a DR coordinate, which has the U-D axis as its DR axis
+    pub fn from_coord(coord: DrCoord) -> Self {
+        let co = coords::co_perm(coord.co as usize).co;
+        let ep = coords::slice_perm(coord.slice as usize).ep;
+
+        Self {
+            corners: co.iter().filter(|&&o| o != 0).count(),
+            edges: SLICE_EDGES.iter().filter(|&&i| !SLICE_EDGES.contains(&ep[i])).count()
+        }
+    }
+}
+
+impl FromStr for DrCase {
+    type Err = &'static str;
+
+    fn from_str(case: &str) -> Result<Self, Self::Err> {
+        let (corners, edges) = case
+            .strip_suffix('e')
+            .and_then(|case| case.split_once('c'))
+            .ok_or("Could not parse DR case")?;
+
+        let case = Self {
+            corners: corners.parse().map_err(|_| "Could not parse DR case")?,
+            edges: edges.parse().map_err(|_| "Could not parse DR case")?
+        };
+
+        // A single corner can't be twisted, and there are only four slice edges
+        if case.corners == 1 || case.corners > 8 || case.edges > 4 {
+            return Err("Impossible DR case");
+        }
+
+        Ok(case)
+    }
+}
+
+impl fmt::Display for DrCase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}c{}e", self.corners, self.edges)
+    }
+}
+
+// The HTR subset of a state with DR, named like "4a1 4e" in FMC: the corner case, then
+// how many U and D layer edges are outside their half-turn slice. The corner case is
+// the number of corners outside their tetrad (the four corners half turns keep them
+// among), a letter, and the fewest quarter turns that solve the corners' part of HTR.
+// Only four bad corners have two variants: "a" when they are all in one layer and "b"
+// when they are split between U and D. Every other case is "c".
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct HtrSubset {
+    pub corners: usize,
+    pub variant: char,
+    pub quarter_turns: usize,
+    pub edges: usize,
+}
+
+impl HtrSubset {
+    // The subset of `perm`, which must have DR on `dr_axis`
+    pub fn of(perm: &Permutation, dr_axis: Axis) -> Self {
+        let perm = perm.conjugate(rotation_to(&[(dr_axis, Axis::UD)]));
+        let bad: Vec<usize> = (0..8).filter(|&i| tetrad(i) != tetrad(perm.cp[i])).collect();
+        let in_u = bad.iter().filter(|&&i| i < 4).count();
+
+        let variant = match (bad.len(), in_u) {
+            (4, 0 | 4) => 'a',
+            (4, _) => 'b',
+            _ => 'c'
+        };
+
+        Self {
+            corners: bad.len(),
+            variant,
+            quarter_turns: corner_quarter_turns()[HtrCoord::from_perm(&perm).corners as usize],
+            edges: (0..12)
+                .filter(|&i| !SLICE_EDGES.contains(&i) && edge_slice(i) != edge_slice(perm.ep[i]))
+                .count()
+        }
+    }
+}
+
+impl FromStr for HtrSubset {
+    type Err = &'static str;
+
+    fn from_str(subset: &str) -> Result<Self, Self::Err> {
+        let error = "Could not parse HTR subset";
+        let (corners, edges) = subset.split_once(' ').ok_or(error)?;
+        let mut chars = corners.chars();
+
+        let subset = Self {
+            corners: chars.next().and_then(|c| c.to_digit(10)).ok_or(error)? as usize,
+            variant: chars.next().filter(|c| ['a', 'b', 'c'].contains(c)).ok_or(error)?,
+            quarter_turns: chars.as_str().parse().map_err(|_| error)?,
+            edges: edges.strip_suffix('e').and_then(|edges| edges.parse().ok()).ok_or(error)?
+        };
+
+        // Corners and edges leave their tetrad or slice in pairs, only four bad corners
+        // come in "a" and "b", and quarter turns of U and D solve the corners in five
+        let impossible = subset.corners % 2 == 1
+            || subset.corners > 8
+            || (subset.corners == 4) == (subset.variant == 'c')
+            || subset.quarter_turns > 5
+            || subset.edges % 2 == 1
+            || subset.edges > 8;
+
+        if impossible {
+            return Err("Impossible HTR subset");
+        }
+
+        Ok(subset)
+    }
+}
+
+impl fmt::Display for HtrSubset {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}{} {}e", self.corners, self.variant, self.quarter_turns, self.edges)
+    }
+}
+
+// The fewest moves to reach HTR from `perm`, which must have DR on `dr_axis`
+pub fn htr_length(perm: &Permutation, dr_axis: Axis) -> usize {
+    let perm = perm.conjugate(rotation_to(&[(dr_axis, Axis::UD)]));
+    PruningTables::get().htr(HtrCoord::from_perm(&perm))
+}
+
+fn tetrad(corner: usize) -> usize {
+    CornerLoc::from_usize(corner).unwrap().ht_class()
+}
+
+fn edge_slice(edge: usize) -> usize {
+    EdgeLoc::from_usize(edge).unwrap().ht_class()
+}
+
+// For each HTR corner coordinate, the fewest quarter turns of U and D that solve it
+// when half turns are free
+fn corner_quarter_turns() -> &'static [usize] {
+    static TABLE: OnceLock<Vec<usize>> = OnceLock::new();
+
+    TABLE.get_or_init(|| {
+        let tables = MoveTables::get();
+        let moves: Vec<Move> = Move::MOVES_LIST.into_iter().filter(|mov| mov.keeps_domino()).collect();
+        let mut turns = vec![usize::MAX; HTR_CORNERS_SIZE];
+        let mut queue = VecDeque::from([(HtrCoord::SOLVED.corners, 0)]);
+
+        while let Some((coord, count)) = queue.pop_front() {
+            if count >= turns[coord as usize] {
+                continue;
+            }
+
+            turns[coord as usize] = count;
+
+            for &mov in &moves {
+                let next = tables.htr_corners.apply(coord, mov);
+
+                match mov.is_halfturn() {
+                    true => queue.push_front((next, count)),
+                    false => queue.push_back((next, count + 1))
+                }
+            }
+        }
+
+        turns
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ htr_length, DrCase, HtrSubset };
+    use crate::algorithm::{ Algorithm, Axis };
+    use crate::permutation::Permutation;
+
+    #[test]
+    fn classifies_dr_cases() {
+        let perm = |alg: &str| Algorithm::from_str(alg).unwrap().execute(Permutation::ID);
+
+        assert_eq!(DrCase::of(&Permutation::ID, Axis::UD), DrCase::SOLVED);
+        assert_eq!(DrCase::of(&perm("R"), Axis::UD).to_string(), "4c2e");
+        assert_eq!(DrCase::of(&perm("R"), Axis::FB).to_string(), "4c2e");
+        assert_eq!(DrCase::of(&perm("R"), Axis::RL), DrCase::SOLVED);
+        assert_eq!(DrCase::of(&perm("R U R'"), Axis::UD).to_string(), "3c1e");
+        assert_eq!(DrCase::by_axis(&perm("F2 U2 R"), Axis::FB), [(Axis::UD, DrCase::of(&perm("F2 U2 R"), Axis::UD)), (Axis::RL, DrCase::SOLVED)]);
+    }
+
+    #[test]
+    fn classifies_htr_subsets() {
+        let perm = |alg: &str| Algorithm::from_str(alg).unwrap().execute(Permutation::ID);
+
+        assert_eq!(HtrSubset::of(&Permutation::ID, Axis::UD).to_string(), "0c0 0e");
+        assert_eq!(HtrSubset::of(&perm("U"), Axis::UD).to_string(), "4a1 4e");
+        assert_eq!(htr_length(&perm("U"), Axis::UD), 1);
+        assert_eq!(htr_length(&perm("R2 U F2 D' L2 U"), Axis::UD), 5);
+        assert_eq!(HtrSubset::of(&perm("R"), Axis::RL), HtrSubset::of(&perm("U"), Axis::UD));
+        assert_eq!("4b3 8e".parse(), Ok(HtrSubset { corners: 4, variant: 'b', quarter_turns: 3, edges: 8 }));
+    }
+
+    #[test]
+    fn parses_cases() {
+        assert_eq!("4c4e".parse(), Ok(DrCase { corners: 4, edges: 4 }));
+        assert!("1c2e".parse::<DrCase>().is_err());
+        assert!("4c".parse::<DrCase>().is_err());
+        assert!("3c2 4e".parse::<HtrSubset>().is_err());
+        assert!("9c2 4e".parse::<HtrSubset>().is_err());
+        assert!("2a1 4e".parse::<HtrSubset>().is_err());
+        assert!("4a1 3e".parse::<HtrSubset>().is_err());
+    }
+}
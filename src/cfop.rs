@@ -0,0 +1,824 @@
+use std::fmt;
+use std::sync::{ Arc, OnceLock };
+
+use crate::algorithm::{ Algorithm, Move };
+use crate::coords::{ PieceMoves, PIECE_STATES };
+use crate::cube::Cube;
+use crate::limits::Limits;
+use crate::metric::Metric;
+use crate::observer::SolverObserver;
+use crate::permutation::Permutation;
+use crate::pipeline::Pipeline;
+use crate::pruning::distance_table;
+use crate::solver::{ SearchState, Side, Step };
+use crate::stage::{ Stage, StageSearch };
+
+// Longest cross or F2L pair worth searching for
+const MAX_STEP_LENGTH: usize = 20;
+
+// What may come before and after a last layer algorithm
+const U_TURNS: [&str; 4] = ["", "U", "U2", "U'"];
+
+const CROSS_SIZE: usize = PIECE_STATES.pow(4);
+
+// The standard last layer algorithms, with the cross on D. They are written with face
+// turns only, so that they rotate and cancel like the searched steps.
+const OLL: [(&str, &str); 57] = [
+    ("OLL 1", "R U2 R2 F R F' U2 R' F R F'"),
+    ("OLL 2", "F R U R' U' F' B U L U' L' B'"),
+    ("OLL 3", "B U L U' L' B' U' F R U R' U' F'"),
+    ("OLL 4", "B U L U' L' B' U F R U R' U' F'"),
+    ("OLL 5", "L' B2 R B R' B L"),
+    ("OLL 6", "L F2 R' F' R F' L'"),
+    ("OLL 7", "L F R' F R F2 L'"),
+    ("OLL 8", "R' F' L F' L' F2 R"),
+    ("OLL 9", "R U R' U' R' F R2 U R' U' F'"),
+    ("OLL 10", "R U R' U R' F R F' R U2 R'"),
+    ("OLL 11", "L F R' F R' D R D' R F2 L'"),
+    ("OLL 12", "R2 L F' R F' R' F2 R F' R L'"),
+    ("OLL 13", "F U R U' R2 F' R U R U' R'"),
+    ("OLL 14", "R' F R U R' F' R F U' F'"),
+    ("OLL 15", "L' B' L R' U' R U L' B L"),
+    ("OLL 16", "L F L' R U R' U' L F' L'"),
+    ("OLL 17", "R U R' U R' F R F' U2 R' F R F'"),
+    ("OLL 18", "L F R' F R F2 L2 B' R B' R' B2 L"),
+    ("OLL 19", "L' R B R B R' B' R2 L F R F'"),
+    ("OLL 20", "L F R' F' R2 L2 B R B' R' B' R' L"),
+    ("OLL 21", "R U2 R' U' R U R' U' R U' R'"),
+    ("OLL 22", "R U2 R2 U' R2 U' R2 U2 R"),
+    ("OLL 23", "R2 D' R U2 R' D R U2 R"),
+    ("OLL 24", "L F R' F' L' F R F'"),
+    ("OLL 25", "F' L F R' F' L' F R"),
+    ("OLL 26", "R U2 R' U' R U' R'"),
+    ("OLL 27", "R U R' U R U2 R'"),
+    ("OLL 28", "L F R' F' R L' U R U' R'"),
+    ("OLL 29", "R U R' U' R U' R' F' U' F R U R'"),
+    ("OLL 30", "F R' F R2 U' R' U' R U R' F2"),
+    ("OLL 31", "R' U' F U R U' R' F' R"),
+    ("OLL 32", "L U F' U' L' U L F L'"),
+    ("OLL 33", "R U R' U' R' F R F'"),
+    ("OLL 34", "R U R2 U' R' F R U R U' F'"),
+    ("OLL 35", "R U2 R2 F R F' R U2 R'"),
+    ("OLL 36", "L' U' L U' L' U L U L F' L' F"),
+    ("OLL 37", "F R' F' R U R U' R'"),
+    ("OLL 38", "R U R' U R U' R' U' R' F R F'"),
+    ("OLL 39", "L F' L' U' L U F U' L'"),
+    ("OLL 40", "R' F R U R' U' F' U R"),
+    ("OLL 41", "R U R' U R U2 R' F R U R' U' F'"),
+    ("OLL 42", "R' U' R U' R' U2 R F R U R' U' F'"),
+    ("OLL 43", "F' U' L' U L F"),
+    ("OLL 44", "F U R U' R' F'"),
+    ("OLL 45", "F R U R' U' F'"),
+    ("OLL 46", "R' U' R' F R F' U R"),
+    ("OLL 47", "R' U' R' F R F' R' F R F' U R"),
+    ("OLL 48", "F R U R' U' R U R' U' F'"),
+    ("OLL 49", "L F' L2 B L2 F L2 B' L"),
+    ("OLL 50", "L' B L2 F' L2 B' L2 F L'"),
+    ("OLL 51", "F U R U' R' U R U' R' F'"),
+    ("OLL 52", "R U R' U R U' B U' B' R'"),
+    ("OLL 53", "L' B' R B' R' B R B' R' B2 L"),
+    ("OLL 54", "L F R' F R F' R' F R F2 L'"),
+    ("OLL 55", "R' F R U R U' R2 F' R2 U' R' U R U R'"),
+    ("OLL 56", "L' B' L U' R' U R U' R' U R L' B L"),
+    ("OLL 57", "R U R' U' R' L F R F' L'"),
+];
+
+const PLL: [(&str, &str); 21] = [
+    ("Aa perm", "R' F R' B2 R F' R' B2 R2"),
+    ("Ab perm", "R2 B2 R F R' B2 R F' R"),
+    ("E perm", "R B' R' F R B R' F' R B R' F R B' R' F'"),
+    ("F perm", "R' U' F' R U R' U' R' F R2 U' R' U' R U R' U R"),
+    ("Ga perm", "R2 U R' U R' U' R U' R2 U' D R' U R D'"),
+    ("Gb perm", "R' U' R U D' R2 U R' U R U' R U' R2 D"),
+    ("Gc perm", "R2 U' R U' R U R' U R2 U D' R U' R' D"),
+    ("Gd perm", "R U R' U' D R2 U' R U' R' U R' U R2 D'"),
+    ("H perm", "R2 L2 D R2 L2 U2 R2 L2 D R2 L2"),
+    ("Ja perm", "R' U L' U2 R U' R' U2 R L"),
+    ("Jb perm", "R U R' F' R U R' U' R' F R2 U' R'"),
+    ("Na perm", "R U R' U R U R' F' R U R' U' R' F R2 U' R' U2 R U' R'"),
+    ("Nb perm", "R' U R U' R' F' U' F R U R' F R' F' R U' R"),
+    ("Ra perm", "R U' R' U' R U R D R' U' R D' R' U2 R'"),
+    ("Rb perm", "R2 F R U R U' R' F' R U2 R' U2 R"),
+    ("T perm", "R U R' U' R' F R2 U' R' U' R U R' F'"),
+    ("Ua perm", "R U' R U R U R U' R' U' R2"),
+    ("Ub perm", "R2 U R U R' U' R' U' R' U R'"),
+    ("V perm", "R' U R' U' B' R' B2 U' B' U B' R B R"),
+    ("Y perm", "F R U' R' U' R U R' F' R U R' U' R' F R F'"),
+    ("Z perm", "R' L F R2 L2 B R2 L2 F R' L D2 R2 L2"),
+];
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Face {
+    U,
+    D,
+    L,
+    R,
+    F,
+    B,
+}
+
+impl Face {
+    pub const ALL: [Face; 6] = [Face::U, Face::D, Face::L, Face::R, Face::F, Face::B];
+
+    // A rotation that brings this face to D
+    fn rotation(self) -> Permutation {
+        let turn = Move::from_str(&self.to_string()).unwrap();
+        Permutation::rotations().into_iter().find(|&rotation| turn.rotate(rotation) == d_turn()).unwrap()
+    }
+}
+
+impl fmt::Display for Face {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Face::U => "U",
+            Face::D => "D",
+            Face::L => "L",
+            Face::R => "R",
+            Face::F => "F",
+            Face::B => "B"
+        })
+    }
+}
+
+// An F2L slot, named after its edge, with the D corner below that edge
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Slot {
+    FR,
+    FL,
+    BL,
+    BR,
+}
+
+impl Slot {
+    pub const ALL: [Slot; 4] = [Slot::FR, Slot::FL, Slot::BL, Slot::BR];
+
+    fn name(self) -> &'static str {
+        match self {
+            Slot::FR => "FR",
+            Slot::FL => "FL",
+            Slot::BL => "BL",
+            Slot::BR => "BR"
+        }
+    }
+
+    // Index of the corner and the edge among those tracked by F2lState
+    fn pieces(self) -> (usize, usize) {
+        match self {
+            Slot::FR => (0, 1),
+            Slot::FL => (1, 2),
+            Slot::BL => (2, 3),
+            Slot::BR => (3, 0)
+        }
+    }
+
+    // Where this slot is once the cube is rotated by `rotation`, which must keep D in place
+    fn rotate(self, rotation: Permutation) -> Slot {
+        let mut probe = Permutation::ID;
+        probe.eo[self.pieces().1 + 4] = 1;
+        let edge = probe.conjugate(rotation).eo.iter().position(|&flip| flip == 1).unwrap();
+
+        Slot::ALL.into_iter().find(|slot| slot.pieces().1 + 4 == edge).unwrap()
+    }
+
+    // A rotation around D that brings this slot to FR
+    fn rotation(self) -> Permutation {
+        Permutation::rotations()
+            .into_iter()
+            .find(|&rotation| d_turn().rotate(rotation) == d_turn() && self.rotate(rotation) == Slot::FR)
+            .unwrap()
+    }
+}
+
+impl fmt::Display for Slot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+// Where each F2L piece is, as location * orientations + orientation: the D corners in
+// CornerLoc order, then the E slice edges and the D edges in EdgeLoc order
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+struct F2lState {
+    corners: [u8; 4],
+    edges: [u8; 8],
+}
+
+impl F2lState {
+    const SOLVED: Self = Self {
+        corners: [12, 15, 18, 21],
+        edges: [8, 10, 12, 14, 16, 18, 20, 22],
+    };
+
+    fn from_perm(perm: &Permutation) -> Self {
+        Self {
+            corners: [4, 5, 6, 7].map(|piece| PieceMoves::corner_state(perm, piece)),
+            edges: [4, 5, 6, 7, 8, 9, 10, 11].map(|piece| PieceMoves::edge_state(perm, piece))
+        }
+    }
+
+    fn cross(&self) -> [u8; 4] {
+        [self.edges[4], self.edges[5], self.edges[6], self.edges[7]]
+    }
+
+    fn has_cross(&self) -> bool {
+        self.cross() == F2lState::SOLVED.cross()
+    }
+
+    fn has_pair(&self, slot: Slot) -> bool {
+        let (corner, edge) = slot.pieces();
+        self.corners[corner] == F2lState::SOLVED.corners[corner] && self.edges[edge] == F2lState::SOLVED.edges[edge]
+    }
+}
+
+impl SearchState for F2lState {
+    fn apply(self, mov: Move) -> Self {
+        let pieces = PieceMoves::get();
+
+        Self {
+            corners: self.corners.map(|corner| pieces.corner(corner, mov)),
+            edges: self.edges.map(|edge| pieces.edge(edge, mov))
+        }
+    }
+}
+
+// The distances to the cross alone and to the cross with the corner or the edge of the
+// FR pair. Other slots are searched rotated to FR.
+struct CfopTables {
+    cross: Vec<u8>,
+    cross_corner: Vec<u8>,
+    cross_edge: Vec<u8>,
+}
+
+impl CfopTables {
+    fn get() -> &'static Self {
+        static TABLES: OnceLock<CfopTables> = OnceLock::new();
+        TABLES.get_or_init(Self::generate)
+    }
+
+    fn generate() -> Self {
+        let pieces = PieceMoves::get();
+        let moves = Vec::from(Move::MOVES_LIST);
+        let solved = cross_index(F2lState::SOLVED.cross());
+
+        let apply_cross = |i: usize, mov: Move| {
+            let mut next = 0;
+
+            for digit in (0..4).rev() {
+                let edge = i / PIECE_STATES.pow(digit) % PIECE_STATES;
+                next = next * PIECE_STATES + pieces.edge(edge as u8, mov) as usize;
+            }
+
+            next
+        };
+
+        let cross = distance_table(CROSS_SIZE, &[solved], &moves, apply_cross);
+        let cross_corner = distance_table(CROSS_SIZE * PIECE_STATES, &[solved * PIECE_STATES + F2lState::SOLVED.corners[0] as usize], &moves, |i, mov| {
+            apply_cross(i / PIECE_STATES, mov) * PIECE_STATES + pieces.corner((i % PIECE_STATES) as u8, mov) as usize
+        });
+        let cross_edge = distance_table(CROSS_SIZE * PIECE_STATES, &[solved * PIECE_STATES + F2lState::SOLVED.edges[1] as usize], &moves, |i, mov| {
+            apply_cross(i / PIECE_STATES, mov) * PIECE_STATES + pieces.edge((i % PIECE_STATES) as u8, mov) as usize
+        });
+
+        Self { cross, cross_corner, cross_edge }
+    }
+
+    fn cross(&self, state: &F2lState) -> usize {
+        self.cross[cross_index(state.cross())] as usize
+    }
+
+    // Lower bound for the cross and the FR pair together
+    fn fr_pair(&self, state: &F2lState) -> usize {
+        let cross = cross_index(state.cross()) * PIECE_STATES;
+        let corner = self.cross_corner[cross + state.corners[0] as usize];
+        let edge = self.cross_edge[cross + state.edges[1] as usize];
+
+        corner.max(edge) as usize
+    }
+}
+
+fn cross_index(cross: [u8; 4]) -> usize {
+    cross.iter().rev().fold(0, |index, &edge| index * PIECE_STATES + edge as usize)
+}
+
+fn d_turn() -> Move {
+    Move::from_str("D").unwrap()
+}
+
+// The four D edges, solved optimally with the cross on `face`
+pub struct CrossStage {
+    pub face: Face,
+}
+
+impl Stage for CrossStage {
+    fn name(&self) -> &'static str {
+        "Cross"
+    }
+
+    fn moves(&self) -> Vec<Move> {
+        Vec::from(Move::MOVES_LIST)
+    }
+
+    fn is_solved(&self, perm: &Permutation) -> bool {
+        F2lState::from_perm(&perm.conjugate(self.face.rotation())).has_cross()
+    }
+
+    fn heuristic(&self, perm: &Permutation) -> usize {
+        CfopTables::get().cross(&F2lState::from_perm(&perm.conjugate(self.face.rotation())))
+    }
+
+    fn allows_niss(&self) -> bool {
+        false
+    }
+
+    fn solutions<'a>(&'a self, state: Permutation, search: &StageSearch) -> Box<dyn Iterator<Item = Algorithm> + 'a> {
+        let tables = CfopTables::get();
+        let rotation = self.face.rotation();
+
+        search.run_rotated(
+            self.name(),
+            F2lState::from_perm(&state.conjugate(rotation)),
+            rotation,
+            &Move::MOVES_LIST,
+            |state| state.has_cross(),
+            |state| tables.cross(&state)
+        )
+    }
+}
+
+// The pair of `slot`, solved optimally while keeping the cross and the pairs of
+// `solved` in place, or putting them back
+pub struct PairStage {
+    pub face: Face,
+    pub slot: Slot,
+    pub solved: Vec<Slot>,
+}
+
+impl PairStage {
+    // The cross on `face` rotated to D, then the slot rotated to FR
+    fn rotation(&self) -> Permutation {
+        self.face.rotation() + self.slot.rotation()
+    }
+
+    // The slots that must be solved, as seen in the rotated cube
+    fn goal(&self) -> impl Fn(F2lState) -> bool + '_ {
+        let rotation = self.slot.rotation();
+        let slots: Vec<Slot> = self.solved.iter().map(|slot| slot.rotate(rotation)).chain([Slot::FR]).collect();
+
+        move |state| state.has_cross() && slots.iter().all(|&slot| state.has_pair(slot))
+    }
+}
+
+impl Stage for PairStage {
+    fn name(&self) -> &'static str {
+        match self.slot {
+            Slot::FR => "FR pair",
+            Slot::FL => "FL pair",
+            Slot::BL => "BL pair",
+            Slot::BR => "BR pair"
+        }
+    }
+
+    fn moves(&self) -> Vec<Move> {
+        Vec::from(Move::MOVES_LIST)
+    }
+
+    fn is_solved(&self, perm: &Permutation) -> bool {
+        self.goal()(F2lState::from_perm(&perm.conjugate(self.rotation())))
+    }
+
+    fn heuristic(&self, perm: &Permutation) -> usize {
+        CfopTables::get().fr_pair(&F2lState::from_perm(&perm.conjugate(self.rotation())))
+    }
+
+    fn allows_niss(&self) -> bool {
+        false
+    }
+
+    fn solutions<'a>(&'a self, state: Permutation, search: &StageSearch) -> Box<dyn Iterator<Item = Algorithm> + 'a> {
+        let tables = CfopTables::get();
+        let rotation = self.rotation();
+
+        search.run_rotated(
+            self.name(),
+            F2lState::from_perm(&state.conjugate(rotation)),
+            rotation,
+            &Move::MOVES_LIST,
+            self.goal(),
+            |state| tables.fr_pair(&state)
+        )
+    }
+}
+
+// A case of the last layer recognised on the cube, with the algorithm that solves it
+// including the U turns needed before and after
+#[derive(Clone, Debug)]
+pub struct LastLayerCase {
+    pub name: &'static str,
+    pub algorithm: Algorithm,
+}
+
+// Orients the last layer with one of the 57 OLL algorithms
+pub struct OllStage {
+    pub face: Face,
+}
+
+impl OllStage {
+    // Every algorithm of the set that orients the last layer of `state`, shortest first.
+    // An oriented last layer is the OLL skip.
+    pub fn cases(&self, state: &Permutation) -> Vec<LastLayerCase> {
+        orienting(state, self.face, &OLL)
+    }
+}
+
+impl Stage for OllStage {
+    fn name(&self) -> &'static str {
+        "OLL"
+    }
+
+    fn moves(&self) -> Vec<Move> {
+        Vec::from(Move::MOVES_LIST)
+    }
+
+    fn is_solved(&self, perm: &Permutation) -> bool {
+        is_oriented(&perm.conjugate(self.face.rotation()))
+    }
+
+    fn allows_niss(&self) -> bool {
+        false
+    }
+
+    fn solutions<'a>(&'a self, state: Permutation, search: &StageSearch) -> Box<dyn Iterator<Item = Algorithm> + 'a> {
+        let progress = search.progress(self.name());
+        let cases = self.cases(&state);
+
+        for case in &cases {
+            progress.solution(&case.algorithm);
+        }
+
+        Box::new(cases.into_iter().map(|case| case.algorithm))
+    }
+}
+
+// Orients the corners of a last layer whose edges are already oriented, with one of the
+// 7 OLL algorithms that keep the edges oriented
+pub struct OcllStage {
+    pub face: Face,
+}
+
+impl OcllStage {
+    // Every OCLL algorithm that orients the last layer of `state`, shortest first. There
+    // are none for a last layer with misoriented edges.
+    pub fn cases(&self, state: &Permutation) -> Vec<LastLayerCase> {
+        let ocll: Vec<_> = OLL.into_iter().filter(|&(_, alg)| keeps_edges_oriented(alg)).collect();
+        orienting(state, self.face, &ocll)
+    }
+}
+
+impl Stage for OcllStage {
+    fn name(&self) -> &'static str {
+        "OCLL"
+    }
+
+    fn moves(&self) -> Vec<Move> {
+        Vec::from(Move::MOVES_LIST)
+    }
+
+    fn is_solved(&self, perm: &Permutation) -> bool {
+        is_oriented(&perm.conjugate(self.face.rotation()))
+    }
+
+    fn allows_niss(&self) -> bool {
+        false
+    }
+
+    fn solutions<'a>(&'a self, state: Permutation, search: &StageSearch) -> Box<dyn Iterator<Item = Algorithm> + 'a> {
+        let progress = search.progress(self.name());
+        let cases = self.cases(&state);
+
+        for case in &cases {
+            progress.solution(&case.algorithm);
+        }
+
+        Box::new(cases.into_iter().map(|case| case.algorithm))
+    }
+}
+
+// Solves an oriented last layer with one of the 21 PLL algorithms
+pub struct PllStage {
+    pub face: Face,
+}
+
+impl PllStage {
+    // Every algorithm of the set that solves `state`, shortest first. A last layer only
+    // off by a U turn is the PLL skip.
+    pub fn cases(&self, state: &Permutation) -> Vec<LastLayerCase> {
+        let state = state.conjugate(self.face.rotation());
+
+        let skip = U_TURNS.map(|auf| LastLayerCase { name: "PLL skip", algorithm: joined(&[auf]) });
+        let cases = PLL.iter().flat_map(|&(name, alg)| {
+            U_TURNS.into_iter().flat_map(move |before| {
+                U_TURNS.map(|after| LastLayerCase { name, algorithm: joined(&[before, alg, after]) })
+            })
+        });
+
+        let solving = |case: &LastLayerCase| case.algorithm.execute(state) == Permutation::ID;
+
+        match skip.into_iter().find(solving) {
+            Some(skip) => rotated(vec![skip], self.face),
+            None => rotated(cases.filter(solving).collect(), self.face)
+        }
+    }
+}
+
+impl Stage for PllStage {
+    fn name(&self) -> &'static str {
+        "PLL"
+    }
+
+    fn moves(&self) -> Vec<Move> {
+        Vec::from(Move::MOVES_LIST)
+    }
+
+    fn is_solved(&self, perm: &Permutation) -> bool {
+        *perm == Permutation::ID
+    }
+
+    fn allows_niss(&self) -> bool {
+        false
+    }
+
+    fn solutions<'a>(&'a self, state: Permutation, search: &StageSearch) -> Box<dyn Iterator<Item = Algorithm> + 'a> {
+        let progress = search.progress(self.name());
+        let cases = self.cases(&state);
+
+        for case in &cases {
+            progress.solution(&case.algorithm);
+        }
+
+        Box::new(cases.into_iter().map(|case| case.algorithm))
+    }
+}
+
+fn is_oriented(perm: &Permutation) -> bool {
+    F2lState::from_perm(perm) == F2lState::SOLVED && perm.co[..4] == [0; 4] && perm.eo[..4] == [0; 4]
+}
+
+// Every algorithm of `algs`, after any U turn, that orients the last layer of `state`
+fn orienting(state: &Permutation, face: Face, algs: &[(&'static str, &'static str)]) -> Vec<LastLayerCase> {
+    let state = state.conjugate(face.rotation());
+
+    if is_oriented(&state) {
+        return vec![LastLayerCase { name: "OLL skip", algorithm: Algorithm::new() }];
+    }
+
+    let cases = algs.iter().flat_map(|&(name, alg)| {
+        U_TURNS.map(|auf| LastLayerCase { name, algorithm: joined(&[auf, alg]) })
+    });
+
+    rotated(cases.filter(|case| is_oriented(&case.algorithm.execute(state))).collect(), face)
+}
+
+fn keeps_edges_oriented(alg: &str) -> bool {
+    Algorithm::from_str(alg).unwrap().execute(Permutation::ID).eo[..4] == [0; 4]
+}
+
+// Shortest first, and turned back from the cube with the cross on D to the cube as it is
+fn rotated(mut cases: Vec<LastLayerCase>, face: Face) -> Vec<LastLayerCase> {
+    let rotation = face.rotation();
+
+    cases.sort_by_key(|case| case.algorithm.len());
+    cases.into_iter().map(|case| LastLayerCase { algorithm: case.algorithm.rotate(-rotation), ..case }).collect()
+}
+
+fn joined(parts: &[&str]) -> Algorithm {
+    Algorithm::from_str(&parts.iter().filter(|part| !part.is_empty()).copied().collect::<Vec<_>>().join(" ")).unwrap().cancel()
+}
+
+pub struct CfopSolution {
+    pub algorithm: Algorithm,
+    pub face: Face,
+    // Cross, the pairs in the order they were solved, OLL and PLL
+    pub steps: Vec<Step>,
+    pub oll: &'static str,
+    pub pll: &'static str,
+}
+
+// Solves like a CFOP solver would, as a reference for human solves: an optimal cross,
+// then each F2L pair optimally, then OLL and PLL from the standard algorithms. The
+// cross is tried on each face of `faces` and the shortest solution is kept. Without a
+// pair order, the pair with the shortest solution is always solved next.
+pub struct CfopSolver {
+    faces: Vec<Face>,
+    pair_order: Option<Vec<Slot>>,
+    observer: Option<Arc<dyn SolverObserver>>,
+    limits: Limits,
+    metric: Metric,
+}
+
+impl Default for CfopSolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CfopSolver {
+    pub fn new() -> Self {
+        Self {
+            faces: Vec::from(Face::ALL),
+            pair_order: None,
+            observer: None,
+            limits: Limits::default(),
+            metric: Metric::default()
+        }
+    }
+
+    pub fn with_faces(mut self, faces: Vec<Face>) -> Self {
+        self.faces = faces;
+        self
+    }
+
+    // Solves the pairs in this order. Panics unless it names each slot once.
+    pub fn with_pair_order(mut self, order: Vec<Slot>) -> Self {
+        assert!(
+            order.len() == Slot::ALL.len() && Slot::ALL.iter().all(|slot| order.contains(slot)),
+            "The pair order must name each slot once"
+        );
+        self.pair_order = Some(order);
+        self
+    }
+
+    pub fn with_observer(mut self, observer: impl SolverObserver + 'static) -> Self {
+        self.observer = Some(Arc::new(observer));
+        self
+    }
+
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn with_metric(mut self, metric: Metric) -> Self {
+        self.metric = metric;
+        self
+    }
+
+    pub fn solve(&self, cube: &Cube) -> Option<CfopSolution> {
+        let mut best: Option<CfopSolution> = None;
+
+        for &face in &self.faces {
+            let Some(solution) = self.solve_on(cube.state.normalize(), face) else {
+                continue;
+            };
+
+            if best.as_ref().is_none_or(|best| solution.algorithm.length(self.metric) < best.algorithm.length(self.metric)) {
+                best = Some(solution);
+            }
+        }
+
+        best
+    }
+
+    fn solve_on(&self, mut state: Permutation, face: Face) -> Option<CfopSolution> {
+        let search = StageSearch::new(MAX_STEP_LENGTH, self.limits.clone(), self.observer.clone(), self.metric);
+        let mut steps = vec![];
+        let mut step = |stage: &dyn Stage, alg: Algorithm, state: &mut Permutation| {
+            *state = alg.execute(*state);
+            steps.push(Step { name: stage.name(), algorithm: alg, side: Side::Normal });
+        };
+
+        let cross = CrossStage { face };
+        step(&cross, cross.solutions(state, &search).next()?, &mut state);
+
+        let mut solved = vec![];
+
+        while solved.len() < Slot::ALL.len() {
+            let remaining: Vec<Slot> = match &self.pair_order {
+                Some(order) => vec![order[solved.len()]],
+                None => Slot::ALL.into_iter().filter(|slot| !solved.contains(slot)).collect()
+            };
+
+            let (slot, alg) = remaining
+                .into_iter()
+                .map(|slot| {
+                    let pair = PairStage { face, slot, solved: solved.clone() };
+                    let alg = pair.solutions(state, &search).next();
+                    alg.map(|alg| (slot, alg))
+                })
+                .collect::<Option<Vec<_>>>()?
+                .into_iter()
+                .min_by_key(|(_, alg)| alg.length(self.metric))?;
+
+            step(&PairStage { face, slot, solved: solved.clone() }, alg, &mut state);
+            solved.push(slot);
+        }
+
+        let oll = OllStage { face }.cases(&state).into_iter().next()?;
+        step(&OllStage { face }, oll.algorithm, &mut state);
+
+        let pll = PllStage { face }.cases(&state).into_iter().next()?;
+        step(&PllStage { face }, pll.algorithm, &mut state);
+
+        Some(CfopSolution { algorithm: Pipeline::linear(&steps), face, steps, oll: oll.name, pll: pll.name })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ CfopSolver, CrossStage, Face, F2lState, OcllStage, OllStage, PllStage, Slot, OLL, PLL };
+    use crate::algorithm::Algorithm;
+    use crate::cube::Cube;
+    use crate::limits::Limits;
+    use crate::metric::Metric;
+    use crate::permutation::Permutation;
+    use crate::stage::{ Stage, StageSearch };
+
+    #[test]
+    fn last_layer_algorithms_keep_f2l() {
+        for (name, alg) in OLL.iter().chain(&PLL) {
+            let perm = Algorithm::from_str(alg).unwrap().execute(Permutation::ID);
+            assert_eq!(F2lState::from_perm(&perm), F2lState::SOLVED, "{name}");
+        }
+    }
+
+    #[test]
+    fn recognises_every_last_layer() {
+        let oll = OllStage { face: Face::D };
+        let ocll = OcllStage { face: Face::D };
+        let pll = PllStage { face: Face::D };
+        let ocll_names = ["OLL skip", "OLL 21", "OLL 22", "OLL 23", "OLL 24", "OLL 25", "OLL 26", "OLL 27"];
+        let orders: Vec<[usize; 4]> = (0..256)
+            .map(|i| [i % 4, i / 4 % 4, i / 16 % 4, i / 64])
+            .filter(|order| (0..4).all(|piece| order.contains(&piece)))
+            .collect();
+
+        for i in 0..81 * 16 {
+            let mut perm = Permutation::ID;
+            (0..4).for_each(|piece| perm.co[piece] = i / 3usize.pow(piece as u32) % 3);
+            (0..4).for_each(|piece| perm.eo[piece] = ((i / 81) >> piece) % 2);
+
+            if perm.co.iter().sum::<usize>() % 3 == 0 && perm.eo.iter().sum::<usize>() % 2 == 0 {
+                assert!(!oll.cases(&perm).is_empty());
+
+                let ocll = ocll.cases(&perm);
+                assert_eq!(ocll.is_empty(), perm.eo[..4] != [0; 4]);
+                assert!(ocll.iter().all(|case| ocll_names.contains(&case.name)));
+            }
+        }
+
+        for corners in &orders {
+            for edges in &orders {
+                let mut perm = Permutation::ID;
+                perm.cp[..4].copy_from_slice(corners);
+                perm.ep[..4].copy_from_slice(edges);
+
+                let parity = |order: &[usize; 4]| (0..4).flat_map(|i| (i..4).map(move |j| (i, j))).filter(|&(i, j)| order[i] > order[j]).count() % 2;
+
+                if parity(corners) == parity(edges) {
+                    assert_eq!(pll.cases(&perm)[0].algorithm.execute(perm), Permutation::ID);
+                }
+            }
+        }
+
+        let t_perm = Algorithm::from_str("R U R' U' R' F R2 U' R' U' R U R' F'").unwrap().execute(Permutation::ID);
+        assert_eq!(pll.cases(&t_perm)[0].name, "T perm");
+    }
+
+    #[test]
+    fn solves_the_cross_on_any_face() {
+        let cube = Cube::new().execute(&Algorithm::from_str("L2 U").unwrap());
+        let search = StageSearch::new(8, Limits::default(), None, Metric::default());
+        let cross = |face| CrossStage { face }.solutions(cube.state, &search).next().unwrap();
+
+        assert_eq!(cross(Face::U).len(), 2);
+        assert_eq!(cross(Face::R).len(), 1);
+        assert_eq!(cross(Face::D).len(), 2);
+        assert!(CrossStage { face: Face::R }.is_solved(&Algorithm::from_str("U'").unwrap().execute(cube.state)));
+    }
+
+    #[test]
+    fn solves_with_cfop() {
+        let scramble = Algorithm::from_str("R U2 F' L D' B2 R' U F2 D L' B U2 R2 F D2 L B' U").unwrap();
+        let cube = Cube::new().execute(&scramble);
+        let order = vec![Slot::BL, Slot::FR, Slot::BR, Slot::FL];
+        let solution = CfopSolver::new().with_faces(vec![Face::D]).with_pair_order(order).solve(&cube).unwrap();
+
+        assert_eq!(solution.algorithm.execute(cube.state), Permutation::ID);
+        assert_eq!(
+            solution.steps.iter().map(|step| step.name).collect::<Vec<_>>(),
+            ["Cross", "BL pair", "FR pair", "BR pair", "FL pair", "OLL", "PLL"]
+        );
+        assert!(OLL.iter().any(|&(name, _)| name == solution.oll) || solution.oll == "OLL skip");
+    }
+
+    #[test]
+    #[should_panic(expected = "each slot once")]
+    fn rejects_a_partial_pair_order() {
+        CfopSolver::new().with_pair_order(vec![Slot::BL, Slot::FR, Slot::BR]);
+    }
+
+    #[test]
+    #[should_panic(expected = "each slot once")]
+    fn rejects_a_repeated_slot_in_the_pair_order() {
+        CfopSolver::new().with_pair_order(vec![Slot::BL, Slot::FR, Slot::BR, Slot::BL]);
+    }
+}
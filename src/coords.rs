@@ -0,0 +1,588 @@
+use std::array;
+use std::sync::OnceLock;
+
+use crate::algorithm::Move;
+use crate::permutation::{ EdgeLoc, Permutation };
+use crate::solver::SearchState;
+
+pub const EO_SIZE: usize = 2048;
+pub const CO_SIZE: usize = 2187;
+pub const SLICE_SIZE: usize = 495;
+pub const CP_SIZE: usize = 40320;
+pub const UD_EDGES_SIZE: usize = 40320;
+pub const E_EDGES_SIZE: usize = 24;
+pub const HTR_CORNERS_SIZE: usize = 420;
+pub const SPLIT_SIZE: usize = 70;
+pub const HT_EDGES_SIZE: usize = 24 * 24 * 24;
+
+// Each single piece is in one of 8 or 12 locations with 3 or 2 orientations
+pub(crate) const PIECE_STATES: usize = 24;
+
+pub(crate) const SLICE_EDGES: [usize; 4] = [EdgeLoc::BR as usize, EdgeLoc::FR as usize, EdgeLoc::FL as usize, EdgeLoc::BL as usize];
+const LAYER_EDGES: [usize; 8] = [
+    EdgeLoc::UR as usize, EdgeLoc::UF as usize, EdgeLoc::UL as usize, EdgeLoc::UB as usize,
+    EdgeLoc::DR as usize, EdgeLoc::DF as usize, EdgeLoc::DL as usize, EdgeLoc::DB as usize
+];
+
+// Transitions of one coordinate under each of Move::MOVES_LIST. Some coordinates are
+// only defined inside a subgroup (e.g. ud_edges after DR); moves leaving that subgroup
+// map to u16::MAX.
+pub struct MoveTable {
+    table: Vec<u16>,
+}
+
+impl MoveTable {
+    const UNDEFINED: u16 = u16::MAX;
+
+    fn new(
+        size: usize,
+        keep: impl Fn(Move) -> bool,
+        decode: impl Fn(usize) -> Permutation,
+        encode: impl Fn(&Permutation) -> usize
+    ) -> Self {
+        let mut table = Vec::with_capacity(size * Move::MOVES_LIST.len());
+
+        for i in 0..size {
+            let perm = decode(i);
+
+            for mov in Move::MOVES_LIST {
+                table.push(if keep(mov) { encode(&mov.execute(perm)) as u16 } else { Self::UNDEFINED });
+            }
+        }
+
+        Self { table }
+    }
+
+    pub fn apply(&self, coord: u16, mov: Move) -> u16 {
+        let next = self.table[coord as usize * Move::MOVES_LIST.len() + mov.index()];
+        debug_assert!(next != Self::UNDEFINED, "Move {} leaves the coordinate's subgroup", mov.to_string());
+        next
+    }
+}
+
+pub struct MoveTables {
+    pub eo: MoveTable,
+    pub co: MoveTable,
+    pub slice: MoveTable,
+    pub cp: MoveTable,
+    pub ud_edges: MoveTable,
+    pub e_edges: MoveTable,
+    pub htr_corners: MoveTable,
+    pub split: MoveTable,
+    pub ht_edges: MoveTable,
+    htr_cosets: Vec<u16>,
+}
+
+impl MoveTables {
+    pub fn get() -> &'static Self {
+        static TABLES: OnceLock<MoveTables> = OnceLock::new();
+        TABLES.get_or_init(Self::generate)
+    }
+
+    fn generate() -> Self {
+        let all = |_| true;
+        let domino = |mov: Move| mov.keeps_domino();
+        let halfturn = |mov: Move| mov.is_halfturn();
+
+        let cp = MoveTable::new(CP_SIZE, all, cp_perm, cp_coord);
+        let (htr_cosets, coset_reps) = htr_cosets(&cp);
+
+        Self {
+            eo: MoveTable::new(EO_SIZE, all, eo_perm, eo_coord),
+            co: MoveTable::new(CO_SIZE, all, co_perm, co_coord),
+            slice: MoveTable::new(SLICE_SIZE, all, slice_perm, slice_coord),
+            ud_edges: MoveTable::new(UD_EDGES_SIZE, domino, ud_edges_perm, ud_edges_coord),
+            e_edges: MoveTable::new(E_EDGES_SIZE, domino, e_edges_perm, e_edges_coord),
+            htr_corners: MoveTable::new(
+                HTR_CORNERS_SIZE,
+                all,
+                |i| Permutation { cp: coset_reps[i], ..Permutation::ID },
+                |perm| htr_cosets[cp_coord(perm)] as usize
+            ),
+            split: MoveTable::new(SPLIT_SIZE, domino, split_perm, split_coord),
+            ht_edges: MoveTable::new(HT_EDGES_SIZE, halfturn, ht_edges_perm, ht_edges_coord),
+            cp,
+            htr_cosets,
+        }
+    }
+}
+
+// Where a single corner or edge goes with each face turn and slice move, indexed by
+// Move::index, for coordinates that follow a few pieces on their own. A piece state is
+// location * orientations + orientation.
+pub(crate) struct PieceMoves {
+    corners: Vec<[u8; 27]>,
+    edges: Vec<[u8; 27]>,
+}
+
+impl PieceMoves {
+    pub(crate) fn get() -> &'static Self {
+        static TABLES: OnceLock<PieceMoves> = OnceLock::new();
+        TABLES.get_or_init(|| Self {
+            corners: Self::generate(3, |perm| (perm.cp, perm.co)),
+            edges: Self::generate(2, |perm| (perm.ep, perm.eo))
+        })
+    }
+
+    pub(crate) fn corner(&self, state: u8, mov: Move) -> u8 {
+        self.corners[state as usize][mov.index()]
+    }
+
+    pub(crate) fn edge(&self, state: u8, mov: Move) -> u8 {
+        self.edges[state as usize][mov.index()]
+    }
+
+    // The state of the corner `piece` in `perm`
+    pub(crate) fn corner_state(perm: &Permutation, piece: usize) -> u8 {
+        let location = perm.cp.iter().position(|&other| other == piece).unwrap();
+        (location * 3 + perm.co[location]) as u8
+    }
+
+    pub(crate) fn edge_state(perm: &Permutation, piece: usize) -> u8 {
+        let location = perm.ep.iter().position(|&other| other == piece).unwrap();
+        (location * 2 + perm.eo[location]) as u8
+    }
+
+    // `pieces` gives the permutation and the orientation of one kind of piece
+    fn generate<const N: usize>(orientations: usize, pieces: impl Fn(Permutation) -> ([usize; N], [usize; N])) -> Vec<[u8; 27]> {
+        let moves: Vec<Move> = Move::MOVES_LIST.into_iter().chain(Move::SLICE_MOVES).collect();
+
+        (0..N * orientations)
+            .map(|state| {
+                let (location, orientation) = (state / orientations, state % orientations);
+                let mut next = [0; 27];
+
+                for &mov in &moves {
+                    let (permutation, twist) = pieces(mov.execute(Permutation::ID));
+                    let to = permutation.iter().position(|&from| from == location).unwrap();
+                    next[mov.index()] = (to * orientations + (orientation + twist[to]) % orientations) as u8;
+                }
+
+                next
+            })
+            .collect()
+    }
+}
+
+// Corner permutations reachable with half turns form a subgroup H of 96 elements.
+// Whether a DR state can reach HTR only depends on the coset H + cp, so every corner
+// permutation is mapped to the index of its coset, along with one representative each.
+fn htr_cosets(cp: &MoveTable) -> (Vec<u16>, Vec<[usize; 8]>) {
+    let mut subgroup = vec![cp_coord(&Permutation::ID) as u16];
+    let mut i = 0;
+
+    while i < subgroup.len() {
+        for mov in Move::MOVES_LIST.into_iter().filter(|mov| mov.is_halfturn()) {
+            let next = cp.apply(subgroup[i], mov);
+
+            if !subgroup.contains(&next) {
+                subgroup.push(next);
+            }
+        }
+
+        i += 1;
+    }
+
+    let subgroup: Vec<[usize; 8]> = subgroup.into_iter().map(|i| perm_unrank(i as usize)).collect();
+    let mut cosets = vec![u16::MAX; CP_SIZE];
+    let mut reps = vec![];
+
+    for i in 0..CP_SIZE {
+        if cosets[i] != u16::MAX {
+            continue;
+        }
+
+        let cp: [usize; 8] = perm_unrank(i);
+
+        for h in &subgroup {
+            let member: [usize; 8] = array::from_fn(|j| h[cp[j]]);
+            cosets[perm_rank(&member)] = reps.len() as u16;
+        }
+
+        reps.push(cp);
+    }
+
+    (cosets, reps)
+}
+
+// Edge orientation relative to F/B, valid everywhere
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct EoCoord(pub u16);
+
+impl EoCoord {
+    pub const SOLVED: Self = Self(0);
+
+    pub fn from_perm(perm: &Permutation) -> Self {
+        Self(eo_coord(perm) as u16)
+    }
+
+    pub fn is_solved(self) -> bool {
+        self == Self::SOLVED
+    }
+}
+
+impl SearchState for EoCoord {
+    fn apply(self, mov: Move) -> Self {
+        Self(MoveTables::get().eo.apply(self.0, mov))
+    }
+}
+
+// Corner orientation and E-slice edge positions relative to U/D, valid everywhere
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct DrCoord {
+    pub co: u16,
+    pub slice: u16,
+}
+
+impl DrCoord {
+    pub const SOLVED: Self = Self { co: 0, slice: 69 };
+
+    pub fn from_perm(perm: &Permutation) -> Self {
+        Self {
+            co: co_coord(perm) as u16,
+            slice: slice_coord(perm) as u16
+        }
+    }
+
+    pub fn is_solved(self) -> bool {
+        self == Self::SOLVED
+    }
+}
+
+impl SearchState for DrCoord {
+    fn apply(self, mov: Move) -> Self {
+        let tables = MoveTables::get();
+
+        Self {
+            co: tables.co.apply(self.co, mov),
+            slice: tables.slice.apply(self.slice, mov)
+        }
+    }
+}
+
+// Corner coset and U/D-layer edge split, valid once DR is solved
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct HtrCoord {
+    pub corners: u16,
+    pub split: u16,
+}
+
+impl HtrCoord {
+    pub const SOLVED: Self = Self { corners: 0, split: 20 };
+
+    pub fn from_perm(perm: &Permutation) -> Self {
+        Self {
+            corners: htr_corners_coord(perm) as u16,
+            split: split_coord(perm) as u16
+        }
+    }
+
+    pub fn is_solved(self) -> bool {
+        self == Self::SOLVED
+    }
+}
+
+impl SearchState for HtrCoord {
+    fn apply(self, mov: Move) -> Self {
+        let tables = MoveTables::get();
+
+        Self {
+            corners: tables.htr_corners.apply(self.corners, mov),
+            split: tables.split.apply(self.split, mov)
+        }
+    }
+}
+
+// Corner permutation and edge permutation within each slice, valid once HTR is solved.
+// Together they describe the whole state.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct HtCoord {
+    pub corners: u16,
+    pub edges: u16,
+}
+
+impl HtCoord {
+    pub const SOLVED: Self = Self { corners: 0, edges: 0 };
+
+    pub fn from_perm(perm: &Permutation) -> Self {
+        Self {
+            corners: cp_coord(perm) as u16,
+            edges: ht_edges_coord(perm) as u16
+        }
+    }
+
+    pub fn is_solved(self) -> bool {
+        self == Self::SOLVED
+    }
+}
+
+impl SearchState for HtCoord {
+    fn apply(self, mov: Move) -> Self {
+        let tables = MoveTables::get();
+
+        Self {
+            corners: tables.cp.apply(self.corners, mov),
+            edges: tables.ht_edges.apply(self.edges, mov)
+        }
+    }
+}
+
+pub fn eo_coord(perm: &Permutation) -> usize {
+    perm.eo[..11].iter().rev().fold(0, |acc, &o| acc * 2 + o)
+}
+
+pub fn eo_perm(coord: usize) -> Permutation {
+    let mut perm = Permutation::ID;
+
+    for i in 0..11 {
+        perm.eo[i] = (coord >> i) & 1;
+    }
+
+    perm.eo[11] = perm.eo[..11].iter().sum::<usize>() % 2;
+    perm
+}
+
+pub fn co_coord(perm: &Permutation) -> usize {
+    perm.co[..7].iter().rev().fold(0, |acc, &o| acc * 3 + o)
+}
+
+pub fn co_perm(coord: usize) -> Permutation {
+    let mut perm = Permutation::ID;
+    let mut coord = coord;
+
+    for i in 0..7 {
+        perm.co[i] = coord % 3;
+        coord /= 3;
+    }
+
+    perm.co[7] = (3 - perm.co[..7].iter().sum::<usize>() % 3) % 3;
+    perm
+}
+
+// Positions of the four E-slice edges
+pub fn slice_coord(perm: &Permutation) -> usize {
+    comb_rank(perm.ep.iter().map(|p| SLICE_EDGES.contains(p)))
+}
+
+pub fn slice_perm(coord: usize) -> Permutation {
+    let mut perm = Permutation::ID;
+    let mut slice = SLICE_EDGES.iter();
+    let mut other = (0..12).filter(|e| !SLICE_EDGES.contains(e));
+
+    for (i, is_slice) in comb_unrank(coord, 12, 4).into_iter().enumerate() {
+        perm.ep[i] = if is_slice { *slice.next().unwrap() } else { other.next().unwrap() };
+    }
+
+    perm
+}
+
+pub fn cp_coord(perm: &Permutation) -> usize {
+    perm_rank(&perm.cp)
+}
+
+pub fn cp_perm(coord: usize) -> Permutation {
+    Permutation { cp: perm_unrank(coord), ..Permutation::ID }
+}
+
+// Permutation of the eight U/D-layer edges, assuming they are all in those layers
+pub fn ud_edges_coord(perm: &Permutation) -> usize {
+    perm_rank(&LAYER_EDGES.map(|i| perm.ep[i]))
+}
+
+pub fn ud_edges_perm(coord: usize) -> Permutation {
+    let mut perm = Permutation::ID;
+
+    for (i, p) in perm_unrank::<8>(coord).into_iter().enumerate() {
+        perm.ep[LAYER_EDGES[i]] = LAYER_EDGES[p];
+    }
+
+    perm
+}
+
+// Permutation of the four E-slice edges, assuming they are all in the slice
+pub fn e_edges_coord(perm: &Permutation) -> usize {
+    perm_rank(&SLICE_EDGES.map(|i| perm.ep[i]))
+}
+
+pub fn e_edges_perm(coord: usize) -> Permutation {
+    let mut perm = Permutation::ID;
+
+    for (i, p) in perm_unrank::<4>(coord).into_iter().enumerate() {
+        perm.ep[SLICE_EDGES[i]] = SLICE_EDGES[p];
+    }
+
+    perm
+}
+
+pub fn htr_corners_coord(perm: &Permutation) -> usize {
+    MoveTables::get().htr_cosets[cp_coord(perm)] as usize
+}
+
+// Which of the eight U/D-layer positions hold the UR, UL, DR and DL edges
+pub fn split_coord(perm: &Permutation) -> usize {
+    comb_rank(LAYER_EDGES.iter().map(|&i| EdgeLoc::from_usize(perm.ep[i]).unwrap().ht_class() == 0))
+}
+
+pub fn split_perm(coord: usize) -> Permutation {
+    let mut perm = Permutation::ID;
+    let mut class0 = LAYER_EDGES.iter().filter(|&&e| EdgeLoc::from_usize(e).unwrap().ht_class() == 0);
+    let mut class1 = LAYER_EDGES.iter().filter(|&&e| EdgeLoc::from_usize(e).unwrap().ht_class() == 1);
+
+    for (i, is_class0) in comb_unrank(coord, 8, 4).into_iter().enumerate() {
+        perm.ep[LAYER_EDGES[i]] = *if is_class0 { class0.next() } else { class1.next() }.unwrap();
+    }
+
+    perm
+}
+
+// Permutation of the edges within each of the three slices, assuming every edge is in its home slice
+pub fn ht_edges_coord(perm: &Permutation) -> usize {
+    (0..3).fold(0, |acc, class| acc * 24 + perm_rank(&ht_slice(class).map(|i| perm.ep[i])))
+}
+
+pub fn ht_edges_perm(coord: usize) -> Permutation {
+    let mut perm = Permutation::ID;
+    let mut coord = coord;
+
+    for class in (0..3).rev() {
+        let slice = ht_slice(class);
+
+        for (i, p) in perm_unrank::<4>(coord % 24).into_iter().enumerate() {
+            perm.ep[slice[i]] = slice[p];
+        }
+
+        coord /= 24;
+    }
+
+    perm
+}
+
+fn ht_slice(class: usize) -> [usize; 4] {
+    let mut slice = (0..12).filter(|&i| EdgeLoc::from_usize(i).unwrap().ht_class() == class);
+    array::from_fn(|_| slice.next().unwrap())
+}
+
+fn binomial(n: usize, k: usize) -> usize {
+    if k > n {
+        return 0;
+    }
+
+    (0..k).fold(1, |acc, i| acc * (n - i) / (i + 1))
+}
+
+fn comb_rank(flags: impl Iterator<Item = bool>) -> usize {
+    let mut rank = 0;
+    let mut k = 0;
+
+    for (i, flag) in flags.enumerate() {
+        if flag {
+            k += 1;
+            rank += binomial(i, k);
+        }
+    }
+
+    rank
+}
+
+fn comb_unrank(rank: usize, n: usize, k: usize) -> Vec<bool> {
+    let mut flags = vec![false; n];
+    let mut rank = rank;
+    let mut k = k;
+
+    for i in (0..n).rev() {
+        if k > 0 && rank >= binomial(i, k) {
+            rank -= binomial(i, k);
+            flags[i] = true;
+            k -= 1;
+        }
+    }
+
+    flags
+}
+
+// Lexicographic rank of a sequence of distinct values
+pub(crate) fn perm_rank(perm: &[usize]) -> usize {
+    (0..perm.len()).fold(0, |acc, i| {
+        let smaller = perm[i + 1..].iter().filter(|&&p| p < perm[i]).count();
+        acc * (perm.len() - i) + smaller
+    })
+}
+
+pub(crate) fn perm_unrank<const N: usize>(rank: usize) -> [usize; N] {
+    let mut digits = [0; N];
+    let mut rank = rank;
+
+    for i in (0..N).rev() {
+        digits[i] = rank % (N - i);
+        rank /= N - i;
+    }
+
+    let mut remaining: Vec<usize> = (0..N).collect();
+    digits.map(|d| remaining.remove(d))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithm::Algorithm;
+
+    #[test]
+    fn coords_round_trip() {
+        for i in [0, 1, 719, 20000, CP_SIZE - 1] {
+            assert_eq!(cp_coord(&cp_perm(i)), i);
+            assert_eq!(ud_edges_coord(&ud_edges_perm(i)), i);
+        }
+
+        for i in 0..EO_SIZE {
+            assert_eq!(eo_coord(&eo_perm(i)), i);
+        }
+
+        for i in 0..SLICE_SIZE {
+            assert_eq!(slice_coord(&slice_perm(i)), i);
+        }
+
+        for i in 0..SPLIT_SIZE {
+            assert_eq!(split_coord(&split_perm(i)), i);
+        }
+
+        for i in [0, 23, 577, HT_EDGES_SIZE - 1] {
+            assert_eq!(ht_edges_coord(&ht_edges_perm(i)), i);
+        }
+    }
+
+    #[test]
+    fn solved_constants_match_identity() {
+        let perm = Permutation::ID;
+        assert_eq!(EoCoord::from_perm(&perm), EoCoord::SOLVED);
+        assert_eq!(DrCoord::from_perm(&perm), DrCoord::SOLVED);
+        assert_eq!(HtrCoord::from_perm(&perm), HtrCoord::SOLVED);
+        assert_eq!(HtCoord::from_perm(&perm), HtCoord::SOLVED);
+    }
+
+    #[test]
+    fn there_are_420_htr_cosets() {
+        let cosets = &MoveTables::get().htr_cosets;
+        assert_eq!(cosets.iter().max(), Some(&(HTR_CORNERS_SIZE as u16 - 1)));
+    }
+
+    #[test]
+    fn stepping_coords_matches_permutation() {
+        let alg = Algorithm::from_str("R U2 F' L D' B2 R' U F2 D L'").unwrap();
+        let perm = alg.execute(Permutation::ID);
+        let eo = alg.moves().iter().fold(EoCoord::from_perm(&Permutation::ID), |coord, &mov| coord.apply(mov));
+        let dr = alg.moves().iter().fold(DrCoord::from_perm(&Permutation::ID), |coord, &mov| coord.apply(mov));
+
+        assert_eq!(eo, EoCoord::from_perm(&perm));
+        assert_eq!(dr, DrCoord::from_perm(&perm));
+
+        let alg = Algorithm::from_str("U R2 D' B2 L2 U2 F2 D").unwrap();
+        let perm = alg.execute(Permutation::ID);
+        let htr = alg.moves().iter().fold(HtrCoord::from_perm(&Permutation::ID), |coord, &mov| coord.apply(mov));
+        assert_eq!(htr, HtrCoord::from_perm(&perm));
+
+        let alg = Algorithm::from_str("U2 R2 F2 D2 L2 B2 U2").unwrap();
+        let perm = alg.execute(Permutation::ID);
+        let ht = alg.moves().iter().fold(HtCoord::from_perm(&Permutation::ID), |coord, &mov| coord.apply(mov));
+        assert_eq!(ht, HtCoord::from_perm(&perm));
+    }
+}
@@ -8,6 +8,12 @@ pub struct Cube {
 pub    state: Permutation,
 }
 
+impl Default for Cube {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Cube {
     const CORNERS: [Corner; 8] = [
         Corner(Color::WHITE, Color::RED, Color::GREEN),
@@ -35,6 +41,9 @@ impl Cube {
         Edge(Color::YELLOW, Color::BLUE)
     ];
 
+    // In the order of Permutation::centers
+    const CENTERS: [Color; 6] = [Color::WHITE, Color::YELLOW, Color::ORANGE, Color::RED, Color::GREEN, Color::BLUE];
+
     pub fn new() -> Self {
         Self {
             state: Permutation::ID
@@ -75,39 +84,41 @@ impl Cube {
             }
         });
 
+        let centers = self.state.centers.map(|face| Cube::CENTERS[face]);
+
         let u: [String; 9] = [
             corners[CornerLoc::UBL as usize].0, edges[EdgeLoc::UB as usize].0, corners[CornerLoc::UBR as usize].0,
-            edges[EdgeLoc::UL as usize].0,      Color::WHITE,                  edges[EdgeLoc::UR as usize].0,
+            edges[EdgeLoc::UL as usize].0,      centers[0],                    edges[EdgeLoc::UR as usize].0,
             corners[CornerLoc::UFL as usize].0, edges[EdgeLoc::UF as usize].0, corners[CornerLoc::UFR as usize].0
         ].map(|color| color.to_string());
 
         let d: [String; 9] = [
             corners[CornerLoc::DFL as usize].0, edges[EdgeLoc::DF as usize].0, corners[CornerLoc::DFR as usize].0,
-            edges[EdgeLoc::DL as usize].0,      Color::YELLOW,                 edges[EdgeLoc::DR as usize].0,
+            edges[EdgeLoc::DL as usize].0,      centers[1],                    edges[EdgeLoc::DR as usize].0,
             corners[CornerLoc::DBL as usize].0, edges[EdgeLoc::DB as usize].0, corners[CornerLoc::DBR as usize].0
         ].map(|color| color.to_string());
 
         let l: [String; 9] = [
             corners[CornerLoc::UBL as usize].1, edges[EdgeLoc::UL as usize].1, corners[CornerLoc::UFL as usize].2,
-            edges[EdgeLoc::BL as usize].1,      Color::ORANGE,                 edges[EdgeLoc::FL as usize].1,
+            edges[EdgeLoc::BL as usize].1,      centers[2],                    edges[EdgeLoc::FL as usize].1,
             corners[CornerLoc::DBL as usize].2, edges[EdgeLoc::DL as usize].1, corners[CornerLoc::DFL as usize].1
         ].map(|color| color.to_string());
 
         let r: [String; 9] = [
             corners[CornerLoc::UFR as usize].1, edges[EdgeLoc::UR as usize].1, corners[CornerLoc::UBR as usize].2,
-            edges[EdgeLoc::FR as usize].1,      Color::RED,                    edges[EdgeLoc::BR as usize].1,
+            edges[EdgeLoc::FR as usize].1,      centers[3],                    edges[EdgeLoc::BR as usize].1,
             corners[CornerLoc::DFR as usize].2, edges[EdgeLoc::DR as usize].1, corners[CornerLoc::DBR as usize].1
         ].map(|color| color.to_string());
 
         let f: [String; 9] = [
             corners[CornerLoc::UFL as usize].1, edges[EdgeLoc::UF as usize].1, corners[CornerLoc::UFR as usize].2,
-            edges[EdgeLoc::FL as usize].0,      Color::GREEN,                  edges[EdgeLoc::FR as usize].0,
+            edges[EdgeLoc::FL as usize].0,      centers[4],                    edges[EdgeLoc::FR as usize].0,
             corners[CornerLoc::DFL as usize].2, edges[EdgeLoc::DF as usize].1, corners[CornerLoc::DFR as usize].1
         ].map(|color| color.to_string());
 
         let b: [String; 9] = [
             corners[CornerLoc::UBR as usize].1, edges[EdgeLoc::UB as usize].1, corners[CornerLoc::UBL as usize].2,
-            edges[EdgeLoc::BR as usize].0,      Color::BLUE,                   edges[EdgeLoc::BL as usize].0,
+            edges[EdgeLoc::BR as usize].0,      centers[5],                    edges[EdgeLoc::BL as usize].0,
             corners[CornerLoc::DBR as usize].2, edges[EdgeLoc::DB as usize].1, corners[CornerLoc::DBL as usize].1
         ].map(|color| color.to_string());
 
@@ -136,6 +147,7 @@ impl Cube {
 }
 
 #[derive(Clone, Copy)]
+#[allow(clippy::upper_case_acronyms)]
 enum Color {
     NONE,
     WHITE,
@@ -147,6 +159,7 @@ enum Color {
 }
 
 impl Color {
+    #[allow(clippy::inherent_to_string)]
     fn to_string(self) -> String {
         format!("\x1b[{}m  \x1b[0m", match self {
             Color::NONE => 0,
@@ -0,0 +1,239 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use crate::algorithm::{ Algorithm, Move };
+use crate::coords::{ HtCoord, MoveTables, CP_SIZE, HT_EDGES_SIZE };
+use crate::observer::Progress;
+use crate::pruning::distance_table;
+use crate::solver::SearchState;
+
+// Number of states reachable with <U2, D2, L2, R2, F2, B2>
+pub const HALF_TURN_STATES: usize = 663_552;
+
+// Corner permutations reachable with half turns
+const HT_CORNERS_SIZE: usize = 96;
+
+// Written at the start of a saved table, so that no other file is read as one
+const MAGIC: &[u8; 8] = b"HTDIST01";
+
+// The distance from solved of every state of the half-turn group, which finishes any
+// HTR state optimally without searching. It is indexed by the position of the corner
+// permutation among the 96 in the group and by the edge coordinate; the indices that
+// pair corners and edges of different parity are never reached.
+pub struct HalfTurnTable {
+    // Position of each corner permutation in the group, u16::MAX outside of it
+    corners: Vec<u16>,
+    distances: Vec<u8>,
+}
+
+impl HalfTurnTable {
+    pub fn get() -> &'static Self {
+        TABLE.get_or_init(Self::generate)
+    }
+
+    // Like `get`, but reads the table from `path` if it is there and writes it there
+    // otherwise, so it is only generated once across runs. A file that isn't a valid
+    // table is overwritten.
+    pub fn get_cached(path: &Path) -> io::Result<&'static Self> {
+        if let Some(table) = TABLE.get() {
+            return Ok(table);
+        }
+
+        let table = Self::load_or_generate(path)?;
+        Ok(TABLE.get_or_init(|| table))
+    }
+
+    fn load_or_generate(path: &Path) -> io::Result<Self> {
+        match Self::load(path) {
+            Ok(table) => Ok(table),
+            Err(error) if matches!(error.kind(), io::ErrorKind::NotFound | io::ErrorKind::InvalidData) => {
+                let table = Self::generate();
+                table.save(path)?;
+                Ok(table)
+            },
+            Err(error) => Err(error)
+        }
+    }
+
+    pub fn generate() -> Self {
+        let (corners, coords) = ht_corners();
+        let moves = half_turns();
+        let solved = index(&corners, HtCoord::SOLVED).unwrap();
+
+        let distances = distance_table(HT_CORNERS_SIZE * HT_EDGES_SIZE, &[solved], &moves, |i, mov| {
+            let coord = HtCoord { corners: coords[i / HT_EDGES_SIZE], edges: (i % HT_EDGES_SIZE) as u16 };
+            index(&corners, coord.apply(mov)).unwrap()
+        });
+
+        Self { corners, distances }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, [MAGIC.as_slice(), &self.distances].concat())
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let bytes = fs::read(path)?;
+
+        let Some(distances) = bytes.strip_prefix(MAGIC).filter(|distances| distances.len() == HT_CORNERS_SIZE * HT_EDGES_SIZE) else {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a half-turn distance table"));
+        };
+
+        Ok(Self { corners: ht_corners().0, distances: Vec::from(distances) })
+    }
+
+    // The optimal number of half turns to solve `coord`, or None if half turns can't
+    pub fn distance(&self, coord: HtCoord) -> Option<usize> {
+        let distance = self.distances[index(&self.corners, coord)?];
+        (distance != u8::MAX).then_some(distance as usize)
+    }
+
+    // Every optimal solution with half turns, with commuting moves in canonical order
+    pub fn solutions(&self, coord: HtCoord) -> Vec<Algorithm> {
+        self.solutions_with_progress(coord, &mut Progress::unobserved())
+    }
+
+    pub(crate) fn solutions_with_progress(&self, coord: HtCoord, progress: &mut Progress) -> Vec<Algorithm> {
+        let mut solutions = vec![];
+
+        if let Some(distance) = self.distance(coord) {
+            progress.depth(distance);
+            self.descend(coord, distance, &half_turns(), &mut Algorithm::new(), &mut solutions, progress);
+        }
+
+        progress.finish();
+        solutions
+    }
+
+    // How many states of the group are at each distance from solved
+    pub fn length_distribution(&self) -> Vec<usize> {
+        let mut counts = vec![];
+
+        for &distance in self.distances.iter().filter(|&&distance| distance != u8::MAX) {
+            let distance = distance as usize;
+            counts.resize(counts.len().max(distance + 1), 0);
+            counts[distance] += 1;
+        }
+
+        counts
+    }
+
+    // Every move that gets one closer to solved is on an optimal solution
+    fn descend(&self, coord: HtCoord, distance: usize, moves: &[Move], alg: &mut Algorithm, solutions: &mut Vec<Algorithm>, progress: &mut Progress) {
+        progress.node();
+
+        if distance == 0 {
+            progress.solution(alg);
+            solutions.push(alg.clone());
+            return;
+        }
+
+        for &mov in moves {
+            if alg.last().is_some_and(|last| !mov.can_follow(last)) {
+                continue;
+            }
+
+            let next = coord.apply(mov);
+
+            if self.distance(next) == Some(distance - 1) {
+                alg.push(mov);
+                self.descend(next, distance - 1, moves, alg, solutions, progress);
+                alg.pop();
+            }
+        }
+    }
+}
+
+static TABLE: OnceLock<HalfTurnTable> = OnceLock::new();
+
+fn half_turns() -> Vec<Move> {
+    Move::MOVES_LIST.into_iter().filter(|mov| mov.is_halfturn()).collect()
+}
+
+// The position of each corner permutation in the group, and the corner coordinate at
+// each position
+fn ht_corners() -> (Vec<u16>, Vec<u16>) {
+    let tables = MoveTables::get();
+    let moves = half_turns();
+    let mut corners = vec![u16::MAX; CP_SIZE];
+    let mut coords = vec![HtCoord::SOLVED.corners];
+    corners[HtCoord::SOLVED.corners as usize] = 0;
+
+    let mut i = 0;
+    while i < coords.len() {
+        for &mov in &moves {
+            let next = tables.cp.apply(coords[i], mov);
+
+            if corners[next as usize] == u16::MAX {
+                corners[next as usize] = coords.len() as u16;
+                coords.push(next);
+            }
+        }
+
+        i += 1;
+    }
+
+    (corners, coords)
+}
+
+fn index(corners: &[u16], coord: HtCoord) -> Option<usize> {
+    let corner = corners[coord.corners as usize];
+    (corner != u16::MAX).then(|| corner as usize * HT_EDGES_SIZE + coord.edges as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::{ HalfTurnTable, HALF_TURN_STATES };
+    use crate::algorithm::Algorithm;
+    use crate::coords::HtCoord;
+    use crate::permutation::Permutation;
+
+    #[test]
+    fn covers_the_whole_group() {
+        let table = HalfTurnTable::get();
+        let counts = table.length_distribution();
+
+        assert_eq!(counts.iter().sum::<usize>(), HALF_TURN_STATES);
+        assert_eq!(counts[..2], [1, 6]);
+        assert_eq!(table.distance(HtCoord::from_perm(&Algorithm::from_str("R").unwrap().execute(Permutation::ID))), None);
+    }
+
+    #[test]
+    fn finishes_optimally() {
+        let table = HalfTurnTable::get();
+        let state = Algorithm::from_str("R2 U2 F2 L2 D2 B2 U2 R2 F2").unwrap().execute(Permutation::ID);
+        let solutions = table.solutions(HtCoord::from_perm(&state));
+        let distance = table.distance(HtCoord::from_perm(&state)).unwrap();
+
+        assert!(!solutions.is_empty());
+        assert!(solutions.iter().all(|alg| alg.len() == distance && alg.execute(state) == Permutation::ID));
+    }
+
+    #[test]
+    fn saves_and_loads() {
+        let table = HalfTurnTable::get();
+        let path = env::temp_dir().join(format!("halfturn-{}.bin", std::process::id()));
+
+        table.save(&path).unwrap();
+        let loaded = HalfTurnTable::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.distances, table.distances);
+    }
+
+    #[test]
+    fn replaces_an_invalid_cache() {
+        let path = env::temp_dir().join(format!("halfturn-invalid-{}.bin", std::process::id()));
+        std::fs::write(&path, b"not a table").unwrap();
+
+        HalfTurnTable::load_or_generate(&path).unwrap();
+        let loaded = HalfTurnTable::load(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.unwrap().distances, HalfTurnTable::get().distances);
+    }
+}
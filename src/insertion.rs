@@ -0,0 +1,273 @@
+use std::collections::HashSet;
+
+use crate::algorithm::Algorithm;
+use crate::cube::Cube;
+use crate::limits::Limits;
+use crate::metric::Metric;
+use crate::optimal::OptimalSolver;
+use crate::permutation::Permutation;
+
+// How many of the best first insertions of a double insertion get a second one
+const FIRST_INSERTIONS: usize = 8;
+
+// An algorithm inserted into a skeleton before the move at `position`
+#[derive(Clone, Debug)]
+pub struct Insertion {
+    pub position: usize,
+    pub algorithm: Algorithm,
+}
+
+// The insertions that finish a skeleton and the solution they make, moves cancelled. The
+// position of a second insertion counts the moves of the first one.
+#[derive(Clone, Debug)]
+pub struct InsertionResult {
+    pub insertions: Vec<Insertion>,
+    pub solution: Algorithm,
+}
+
+// Finds where to insert short algorithms into a skeleton so that it solves the cube.
+// What the skeleton leaves unsolved is split into cycles of pieces: one cycle, like a
+// corner or an edge 3-cycle, takes a single insertion and two cycles a double one. The
+// algorithms tried at each position are every optimal solution of the case as seen
+// from there, plus those up to `slack` moves longer, and the results that cancel the
+// most come first.
+pub struct InsertionFinder {
+    metric: Metric,
+    slack: usize,
+    results: usize,
+    limits: Limits,
+}
+
+impl Default for InsertionFinder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InsertionFinder {
+    pub fn new() -> Self {
+        Self {
+            metric: Metric::default(),
+            slack: 0,
+            results: 10,
+            limits: Limits::default()
+        }
+    }
+
+    pub fn with_metric(mut self, metric: Metric) -> Self {
+        self.metric = metric;
+        self
+    }
+
+    pub fn with_slack(mut self, slack: usize) -> Self {
+        self.slack = slack;
+        self
+    }
+
+    // How many results `find` returns at most
+    pub fn with_results(mut self, results: usize) -> Self {
+        self.results = results;
+        self
+    }
+
+    // Applies to the search for the algorithms of each position
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    // The best ways to finish `skeleton` on `scramble` with one insertion per cycle left,
+    // or none if that takes more than two
+    pub fn find(&self, scramble: &Algorithm, skeleton: &Algorithm) -> Vec<InsertionResult> {
+        let residual = skeleton.execute(scramble.execute(Permutation::ID));
+
+        match cycles(residual)[..] {
+            [] => vec![InsertionResult { insertions: vec![], solution: skeleton.cancel() }],
+            [_] => self.single(skeleton, residual),
+            [first, second] => self.double(skeleton, first, second),
+            _ => vec![]
+        }
+    }
+
+    fn single(&self, skeleton: &Algorithm, residual: Permutation) -> Vec<InsertionResult> {
+        let results = self.insertions(skeleton, residual)
+            .into_iter()
+            .map(|(insertion, alg)| InsertionResult { insertions: vec![insertion], solution: alg.cancel() })
+            .collect();
+
+        self.best(results)
+    }
+
+    // Each cycle can go first. The other one is left for the second insertion, which is
+    // only tried after the first insertions that cancel the most.
+    fn double(&self, skeleton: &Algorithm, first: Permutation, second: Permutation) -> Vec<InsertionResult> {
+        let mut results = vec![];
+
+        for (first, second) in [(first, second), (second, first)] {
+            let mut insertions = self.insertions(skeleton, first);
+            insertions.sort_by_cached_key(|(_, alg)| alg.cancel().length(self.metric));
+
+            for (insertion, alg) in insertions.into_iter().take(FIRST_INSERTIONS) {
+                for result in self.single(&alg, second) {
+                    results.push(InsertionResult {
+                        insertions: vec![insertion.clone(), result.insertions[0].clone()],
+                        solution: result.solution
+                    });
+                }
+            }
+        }
+
+        self.best(results)
+    }
+
+    // Every insertion that undoes `cycle`, a part of what the skeleton leaves unsolved,
+    // along with the skeleton it makes (moves not cancelled)
+    fn insertions(&self, skeleton: &Algorithm, cycle: Permutation) -> Vec<(Insertion, Algorithm)> {
+        let mut insertions = vec![];
+
+        for position in 0..=skeleton.len() {
+            let (before, after) = skeleton.moves().split_at(position);
+            let after = Algorithm::from_moves(after);
+
+            // Inserting X here changes the end state by X conjugated by the moves after it
+            let target = (-cycle).conjugate(-after.execute(Permutation::ID));
+
+            for algorithm in self.algorithms(target) {
+                let mut alg = Algorithm::from_moves(before);
+                alg.append(&algorithm).append(&after);
+                insertions.push((Insertion { position, algorithm }, alg));
+            }
+        }
+
+        insertions
+    }
+
+    // The algorithms whose permutation is `target`
+    fn algorithms(&self, target: Permutation) -> Vec<Algorithm> {
+        let solver = OptimalSolver::new().with_metric(self.metric).with_limits(self.limits.clone());
+        let cube = Cube { state: -target };
+
+        let Some(optimal) = solver.solve(&cube).found() else {
+            return vec![];
+        };
+
+        solver.solutions(&cube, optimal.length(self.metric) + self.slack).collect()
+    }
+
+    // The shortest distinct solutions first
+    fn best(&self, mut results: Vec<InsertionResult>) -> Vec<InsertionResult> {
+        let mut seen = HashSet::new();
+
+        results.sort_by_cached_key(|result| result.solution.length(self.metric));
+        results.retain(|result| seen.insert(result.solution.to_string()));
+        results.truncate(self.results);
+        results
+    }
+}
+
+// What `perm` does split into its cycles of corners and of edges, each as a state of its
+// own. Cycles that aren't states on their own, like a single twisted corner or a swap,
+// are kept together in one more part.
+fn cycles(perm: Permutation) -> Vec<Permutation> {
+    let mut parts = vec![];
+    let mut rest = Permutation::ID;
+
+    let corners = piece_cycles(&perm.cp, |i| perm.co[i] != 0);
+    let edges = piece_cycles(&perm.ep, |i| perm.eo[i] != 0);
+
+    for cycle in corners {
+        let mut part = Permutation::ID;
+
+        for &i in &cycle {
+            part.cp[i] = perm.cp[i];
+            part.co[i] = perm.co[i];
+        }
+
+        let legal = cycle.len() % 2 == 1 && cycle.iter().map(|&i| perm.co[i]).sum::<usize>() % 3 == 0;
+        if legal { parts.push(part) } else { rest = rest + part }
+    }
+
+    for cycle in edges {
+        let mut part = Permutation::ID;
+
+        for &i in &cycle {
+            part.ep[i] = perm.ep[i];
+            part.eo[i] = perm.eo[i];
+        }
+
+        let legal = cycle.len() % 2 == 1 && cycle.iter().map(|&i| perm.eo[i]).sum::<usize>() % 2 == 0;
+        if legal { parts.push(part) } else { rest = rest + part }
+    }
+
+    if rest != Permutation::ID {
+        parts.push(rest);
+    }
+
+    parts
+}
+
+// The locations of each cycle of pieces that aren't solved, `twisted` telling whether
+// the piece at a location is misoriented
+pub(crate) fn piece_cycles<const N: usize>(pieces: &[usize; N], twisted: impl Fn(usize) -> bool) -> Vec<Vec<usize>> {
+    let mut seen = [false; N];
+    let mut cycles = vec![];
+
+    for start in 0..N {
+        if seen[start] || (pieces[start] == start && !twisted(start)) {
+            continue;
+        }
+
+        let mut cycle = vec![];
+        let mut i = start;
+
+        while !seen[i] {
+            seen[i] = true;
+            cycle.push(i);
+            i = pieces[i];
+        }
+
+        cycles.push(cycle);
+    }
+
+    cycles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ cycles, InsertionFinder };
+    use crate::algorithm::Algorithm;
+    use crate::permutation::Permutation;
+
+    #[test]
+    fn splits_into_cycles() {
+        let perm = |alg: &str| Algorithm::from_str(alg).unwrap().execute(Permutation::ID);
+
+        assert_eq!(cycles(Permutation::ID).len(), 0);
+        assert_eq!(cycles(perm("R U R' D R U' R' D'")).len(), 1);
+        assert_eq!(cycles(perm("R U R' D R U' R' D' R2 U R U R' U' R' U' R' U R'")).len(), 2);
+        assert_eq!(cycles(perm("R U R' U' R' F R2 U' R' U' R U R' F'")).len(), 1);
+    }
+
+    #[test]
+    fn finds_single_insertions() {
+        let scramble = Algorithm::from_str("F2 R U R' D R U' R' D' B2").unwrap();
+        let skeleton = Algorithm::from_str("B2 F2").unwrap();
+        let results = InsertionFinder::new().find(&scramble, &skeleton);
+        let state = scramble.execute(Permutation::ID);
+
+        assert!(!results.is_empty());
+        assert!(results.iter().all(|result| result.insertions.len() == 1 && result.solution.execute(state) == Permutation::ID));
+        assert!(results[0].solution.len() <= 10);
+        assert!(results.is_sorted_by_key(|result| result.solution.len()));
+    }
+
+    #[test]
+    fn finds_double_insertions() {
+        let scramble = Algorithm::from_str("R U R' D R U' R' D' R2 U R U R' U' R' U' R' U R'").unwrap();
+        let results = InsertionFinder::new().with_results(3).find(&scramble, &Algorithm::new());
+        let state = scramble.execute(Permutation::ID);
+
+        assert!(!results.is_empty());
+        assert!(results.iter().all(|result| result.insertions.len() == 2 && result.solution.execute(state) == Permutation::ID));
+    }
+}
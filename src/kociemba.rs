@@ -0,0 +1,300 @@
+use std::collections::HashMap;
+use std::sync::{ Arc, Mutex, OnceLock };
+use std::time::{ Duration, Instant };
+
+use crate::algorithm::{ Algorithm, Move };
+use crate::coords::{
+    self, DrCoord, EoCoord, MoveTables,
+    CO_SIZE, CP_SIZE, EO_SIZE, E_EDGES_SIZE, SLICE_SIZE, UD_EDGES_SIZE
+};
+use crate::cube::Cube;
+use crate::leave::Leave;
+use crate::limits::Limits;
+use crate::observer::SolverObserver;
+use crate::permutation::Permutation;
+use crate::pruning;
+use crate::solver::{ SearchState, Solver };
+
+const MAX_PHASE1_LENGTH: usize = 12;
+const MAX_PHASE2_LENGTH: usize = 18;
+
+// Phase 1 uses all moves to reach the domino subgroup, so it can't reuse the DR table
+// built for EO-preserving moves. Phase 2 only uses domino moves.
+pub struct KociembaTables {
+    co_slice: Vec<u8>,
+    eo_slice: Vec<u8>,
+    phase2: Phase2Tables,
+}
+
+impl KociembaTables {
+    pub fn get() -> &'static Self {
+        static TABLES: OnceLock<KociembaTables> = OnceLock::new();
+        TABLES.get_or_init(Self::generate)
+    }
+
+    fn generate() -> Self {
+        let tables = MoveTables::get();
+
+        let moves = Vec::from(Move::MOVES_LIST);
+        let co_slice = pruning::distance_table(
+            CO_SIZE * SLICE_SIZE,
+            &[DrCoord::SOLVED.co as usize * SLICE_SIZE + DrCoord::SOLVED.slice as usize],
+            &moves,
+            |i, mov| {
+                let (co, slice) = ((i / SLICE_SIZE) as u16, (i % SLICE_SIZE) as u16);
+                tables.co.apply(co, mov) as usize * SLICE_SIZE + tables.slice.apply(slice, mov) as usize
+            }
+        );
+        let eo_slice = pruning::distance_table(
+            EO_SIZE * SLICE_SIZE,
+            &[EoCoord::SOLVED.0 as usize * SLICE_SIZE + DrCoord::SOLVED.slice as usize],
+            &moves,
+            |i, mov| {
+                let (eo, slice) = ((i / SLICE_SIZE) as u16, (i % SLICE_SIZE) as u16);
+                tables.eo.apply(eo, mov) as usize * SLICE_SIZE + tables.slice.apply(slice, mov) as usize
+            }
+        );
+
+        Self { co_slice, eo_slice, phase2: Phase2Tables::generate(Leave::default()) }
+    }
+
+    pub fn phase1(&self, (eo, dr): (EoCoord, DrCoord)) -> usize {
+        let co_slice = self.co_slice[dr.co as usize * SLICE_SIZE + dr.slice as usize];
+        let eo_slice = self.eo_slice[eo.0 as usize * SLICE_SIZE + dr.slice as usize];
+
+        co_slice.max(eo_slice) as usize
+    }
+
+    pub fn phase2(&self, coord: Phase2Coord) -> usize {
+        self.phase2.distance(coord)
+    }
+}
+
+// Distances inside the domino subgroup with domino moves, from the corners and from the
+// U/D-layer edges, each along with the E-slice edges. They are to the nearest state that
+// `leave` allows, which is only the solved one for the default allowance. Either part
+// is at 0 only if its pieces are within the allowance, so both are for those states.
+pub struct Phase2Tables {
+    cp_e_edges: Vec<u8>,
+    ud_e_edges: Vec<u8>,
+}
+
+impl Phase2Tables {
+    // Tables are built once for each allowance
+    pub fn leaving(leave: Leave) -> Arc<Self> {
+        static TABLES: OnceLock<Mutex<HashMap<Leave, Arc<Phase2Tables>>>> = OnceLock::new();
+
+        let mut tables = TABLES.get_or_init(Default::default).lock().unwrap();
+        tables.entry(leave).or_insert_with(|| Arc::new(Self::generate(leave))).clone()
+    }
+
+    fn generate(leave: Leave) -> Self {
+        let tables = MoveTables::get();
+        let moves: Vec<Move> = Move::MOVES_LIST.into_iter().filter(|mov| mov.keeps_domino()).collect();
+
+        let unsolved = |perm: Permutation| {
+            (0..8).filter(|&i| perm.cp[i] != i).count() + (0..12).filter(|&i| perm.ep[i] != i).count()
+        };
+        let corners: Vec<usize> = (0..CP_SIZE).map(|i| unsolved(coords::cp_perm(i))).collect();
+        let ud_edges: Vec<usize> = (0..UD_EDGES_SIZE).map(|i| unsolved(coords::ud_edges_perm(i))).collect();
+        let e_edges: Vec<usize> = (0..E_EDGES_SIZE).map(|i| unsolved(coords::e_edges_perm(i))).collect();
+        let goals = |size: usize, allowed: &dyn Fn(usize, usize) -> bool| -> Vec<usize> {
+            (0..size * E_EDGES_SIZE).filter(|&i| allowed(i / E_EDGES_SIZE, i % E_EDGES_SIZE)).collect()
+        };
+
+        let cp_e_edges = pruning::distance_table(
+            CP_SIZE * E_EDGES_SIZE,
+            &goals(CP_SIZE, &|cp, e| corners[cp] <= leave.corners && e_edges[e] <= leave.edges),
+            &moves,
+            |i, mov| {
+                let (cp, e_edges) = ((i / E_EDGES_SIZE) as u16, (i % E_EDGES_SIZE) as u16);
+                tables.cp.apply(cp, mov) as usize * E_EDGES_SIZE + tables.e_edges.apply(e_edges, mov) as usize
+            }
+        );
+
+        let ud_e_edges = pruning::distance_table(
+            UD_EDGES_SIZE * E_EDGES_SIZE,
+            &goals(UD_EDGES_SIZE, &|ud, e| ud_edges[ud] + e_edges[e] <= leave.edges),
+            &moves,
+            |i, mov| {
+                let (ud_edges, e_edges) = ((i / E_EDGES_SIZE) as u16, (i % E_EDGES_SIZE) as u16);
+                tables.ud_edges.apply(ud_edges, mov) as usize * E_EDGES_SIZE + tables.e_edges.apply(e_edges, mov) as usize
+            }
+        );
+
+        Self { cp_e_edges, ud_e_edges }
+    }
+
+    pub fn distance(&self, coord: Phase2Coord) -> usize {
+        let cp = self.cp_e_edges[coord.cp as usize * E_EDGES_SIZE + coord.e_edges as usize];
+        let ud_edges = self.ud_e_edges[coord.ud_edges as usize * E_EDGES_SIZE + coord.e_edges as usize];
+
+        cp.max(ud_edges) as usize
+    }
+}
+
+// Permutation coordinates inside the domino subgroup, which describe the whole state
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Phase2Coord {
+    pub cp: u16,
+    pub ud_edges: u16,
+    pub e_edges: u16,
+}
+
+impl Phase2Coord {
+    pub fn from_perm(perm: &Permutation) -> Self {
+        Self {
+            cp: coords::cp_coord(perm) as u16,
+            ud_edges: coords::ud_edges_coord(perm) as u16,
+            e_edges: coords::e_edges_coord(perm) as u16
+        }
+    }
+}
+
+impl SearchState for Phase2Coord {
+    fn apply(self, mov: Move) -> Self {
+        let tables = MoveTables::get();
+
+        Self {
+            cp: tables.cp.apply(self.cp, mov),
+            ud_edges: tables.ud_edges.apply(self.ud_edges, mov),
+            e_edges: tables.e_edges.apply(self.e_edges, mov)
+        }
+    }
+}
+
+// Kociemba's two-phase algorithm: every way of reaching the domino subgroup
+// <U, D, R2, L2, F2, B2>, shortest first, followed by an optimal solve inside it.
+// Phase 1 solutions that end in a domino move are skipped, since phase 2 could have
+// made that move. Stops as soon as a solution reaches the target length or the time
+// runs out, also in the middle of either search.
+pub struct TwoPhaseSolver {
+    target_length: usize,
+    timeout: Duration,
+    observer: Option<Arc<dyn SolverObserver>>,
+}
+
+impl Default for TwoPhaseSolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TwoPhaseSolver {
+    pub fn new() -> Self {
+        Self {
+            target_length: 20,
+            timeout: Duration::from_secs(1),
+            observer: None
+        }
+    }
+
+    pub fn with_target_length(mut self, target_length: usize) -> Self {
+        self.target_length = target_length;
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn with_observer(mut self, observer: impl SolverObserver + 'static) -> Self {
+        self.observer = Some(Arc::new(observer));
+        self
+    }
+
+    pub fn solve(&self, cube: &Cube) -> Option<Algorithm> {
+        let deadline = Instant::now() + self.timeout;
+        let limits = Limits { deadline: Some(deadline), ..Limits::default() };
+        let tables = KociembaTables::get();
+        let solved = Phase2Coord::from_perm(&Permutation::ID);
+
+        let phase1_moves = Vec::from(Move::MOVES_LIST);
+        let phase2_moves: Vec<Move> = phase1_moves.iter().copied().filter(|mov| mov.keeps_domino()).collect();
+
+        let start = cube.state.normalize();
+        let phase1 = Solver::search_all(
+            (EoCoord::from_perm(&start), DrCoord::from_perm(&start)),
+            &phase1_moves,
+            |(eo, dr)| eo.is_solved() && dr.is_solved(),
+            |coord| tables.phase1(coord),
+            MAX_PHASE1_LENGTH
+        ).with_limits(limits.clone()).observe("Phase 1", self.observer.clone());
+
+        let mut best: Option<Algorithm> = None;
+
+        for (phase1, _) in phase1 {
+            if phase1.last().is_some_and(|last| last.keeps_domino()) {
+                continue;
+            }
+
+            let max_length = match &best {
+                Some(best) if phase1.len() >= best.len() => break,
+                Some(best) => best.len() - phase1.len() - 1,
+                None => MAX_PHASE2_LENGTH
+            };
+
+            let state = phase1.execute(start);
+            let phase2 = Solver::search_all(
+                Phase2Coord::from_perm(&state),
+                &phase2_moves,
+                move |coord| coord == solved,
+                |coord| tables.phase2(coord),
+                max_length
+            ).with_limits(limits.clone()).observe("Phase 2", self.observer.clone()).next();
+
+            // The last phase 1 move can still merge with the first one of phase 2
+            if let Some((phase2, _)) = phase2 {
+                let mut alg = phase1;
+                alg.append(&phase2);
+                best = Some(alg.cancel());
+            }
+
+            if best.as_ref().is_some_and(|best| best.len() <= self.target_length) || Instant::now() >= deadline {
+                break;
+            }
+        }
+
+        best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn solves_random_state() {
+        let scramble = Algorithm::from_str("D2 F2 U' B2 R2 U' L2 D R2 U2 L2 B' L' U2 F' R U' F2 D' L' R").unwrap();
+        let cube = Cube::new().execute(&scramble);
+        let solution = TwoPhaseSolver::new().with_target_length(22).solve(&cube).unwrap();
+
+        assert_eq!(solution.execute(cube.state), Permutation::ID);
+        assert!(solution.len() <= 22);
+        assert_eq!(solution.cancel().len(), solution.len());
+    }
+
+    #[test]
+    fn stops_searching_at_the_deadline() {
+        let scramble = Algorithm::from_str("D2 F2 U' B2 R2 U' L2 D R2 U2 L2 B' L' U2 F' R U' F2 D' L' R").unwrap();
+        let cube = Cube::new().execute(&scramble);
+        KociembaTables::get();
+
+        let start = Instant::now();
+        let solution = TwoPhaseSolver::new().with_target_length(0).with_timeout(Duration::from_millis(100)).solve(&cube);
+
+        assert!(start.elapsed() < Duration::from_millis(500));
+        assert!(solution.is_none_or(|solution| solution.execute(cube.state) == Permutation::ID));
+    }
+
+    #[test]
+    fn solves_domino_state_in_phase2() {
+        let scramble = Algorithm::from_str("U R2 D' F2 L2 U2").unwrap();
+        let cube = Cube::new().execute(&scramble);
+        let solution = TwoPhaseSolver::new().with_target_length(6).solve(&cube).unwrap();
+
+        assert_eq!(solution.execute(cube.state), Permutation::ID);
+        assert_eq!(solution.len(), 6);
+    }
+}
@@ -0,0 +1,84 @@
+use std::fmt;
+
+use crate::insertion::piece_cycles;
+use crate::permutation::{ CornerLoc, EdgeLoc, Permutation };
+
+// How many pieces a solution may leave unsolved, e.g. three corners for a skeleton that
+// a corner 3-cycle insertion finishes. A piece is solved when it is home and oriented.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Leave {
+    pub corners: usize,
+    pub edges: usize,
+}
+
+impl Leave {
+    pub fn allows(self, perm: &Permutation) -> bool {
+        let corners = (0..8).filter(|&i| perm.cp[i] != i || perm.co[i] != 0).count();
+        let edges = (0..12).filter(|&i| perm.ep[i] != i || perm.eo[i] != 0).count();
+
+        corners <= self.corners && edges <= self.edges
+    }
+}
+
+// Pieces left unsolved that cycle among themselves: the locations in the order the
+// pieces move, and how much the cycle twists corners or flips edges in total
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Cycle {
+    Corners(Vec<CornerLoc>, usize),
+    Edges(Vec<EdgeLoc>, usize),
+}
+
+impl Cycle {
+    // Every unsolved cycle of `perm`, corners first
+    pub fn all(perm: &Permutation) -> Vec<Cycle> {
+        let corners = piece_cycles(&perm.cp, |i| perm.co[i] != 0).into_iter().map(|cycle| {
+            let twist = cycle.iter().map(|&i| perm.co[i]).sum::<usize>() % 3;
+            Cycle::Corners(cycle.into_iter().map(|i| CornerLoc::from_usize(i).unwrap()).collect(), twist)
+        });
+
+        let edges = piece_cycles(&perm.ep, |i| perm.eo[i] != 0).into_iter().map(|cycle| {
+            let flip = cycle.iter().map(|&i| perm.eo[i]).sum::<usize>() % 2;
+            Cycle::Edges(cycle.into_iter().map(|i| EdgeLoc::from_usize(i).unwrap()).collect(), flip)
+        });
+
+        corners.chain(edges).collect()
+    }
+}
+
+// Like "(UFR UBL DFL)", followed by "+" or "-" for a cycle that twists its corners
+// clockwise or counterclockwise and "'" for one that flips its edges
+impl fmt::Display for Cycle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (names, suffix): (Vec<String>, &str) = match self {
+            Cycle::Corners(corners, twist) => (
+                corners.iter().map(|corner| format!("{corner:?}")).collect(),
+                ["", "+", "-"][*twist]
+            ),
+            Cycle::Edges(edges, flip) => (
+                edges.iter().map(|edge| format!("{edge:?}")).collect(),
+                ["", "'"][*flip]
+            )
+        };
+
+        write!(f, "({}){suffix}", names.join(" "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ Cycle, Leave };
+    use crate::algorithm::Algorithm;
+    use crate::permutation::Permutation;
+
+    #[test]
+    fn describes_what_is_left() {
+        let perm = Algorithm::from_str("R U R' D R U' R' D'").unwrap().execute(Permutation::ID);
+        let cycles = Cycle::all(&perm);
+
+        assert_eq!(cycles.len(), 1);
+        assert!(matches!(&cycles[0], Cycle::Corners(corners, 0) if corners.len() == 3));
+        assert!(Leave { corners: 3, edges: 0 }.allows(&perm));
+        assert!(!Leave { corners: 2, edges: 5 }.allows(&perm));
+        assert!(Cycle::all(&Permutation::ID).is_empty());
+    }
+}
@@ -1,4 +1,22 @@
 pub mod algorithm;
+pub mod analysis;
+pub mod cfop;
+pub mod coords;
 pub mod cube;
+pub mod halfturn;
+pub mod insertion;
+pub mod kociemba;
+pub mod leave;
+pub mod limits;
+pub mod metric;
+pub mod observer;
+pub mod optimal;
 pub mod permutation;
+pub mod pipeline;
+pub mod pruning;
+pub mod roux;
 pub mod solver;
+pub mod stage;
+pub mod symmetry;
+pub mod zz;
+mod transposition;
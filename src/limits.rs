@@ -0,0 +1,106 @@
+use std::sync::Arc;
+use std::sync::atomic::{ AtomicBool, Ordering };
+use std::time::Instant;
+
+// Bounds on a single search. A search that hits any of them gives up and reports
+// SearchOutcome::BudgetExhausted rather than running on forever.
+#[derive(Clone, Debug, Default)]
+pub struct Limits {
+    // Longest solution to look for
+    pub max_depth: Option<usize>,
+    // Nodes visited by each search
+    pub max_nodes: Option<u64>,
+    pub deadline: Option<Instant>,
+    pub cancellation: Option<CancellationToken>,
+    // Bytes for a transposition table, which remembers the states searched without
+    // success so that reaching them again through other moves costs nothing. Searches
+    // run without one if None. Threads share the memory out between them.
+    pub transposition_memory: Option<usize>,
+}
+
+impl Limits {
+    // Checking the clock on every node would cost more than the node itself
+    const DEADLINE_INTERVAL: u64 = 1024;
+
+    pub(crate) fn allows_depth(&self, depth: usize) -> bool {
+        self.max_depth.is_none_or(|max_depth| depth <= max_depth)
+    }
+
+    pub(crate) fn allows_nodes(&self, nodes: u64) -> bool {
+        if self.max_nodes.is_some_and(|max_nodes| nodes > max_nodes) {
+            return false;
+        }
+
+        if self.cancellation.as_ref().is_some_and(CancellationToken::is_cancelled) {
+            return false;
+        }
+
+        !nodes.is_multiple_of(Self::DEADLINE_INTERVAL) || self.deadline.is_none_or(|deadline| Instant::now() < deadline)
+    }
+}
+
+// Shared flag that stops every search using it, from any thread. A child token is
+// also cancelled with its parent, but cancelling the child leaves the parent alone.
+#[derive(Clone, Debug)]
+pub struct CancellationToken {
+    // The token's own flag first, then those of its ancestors
+    flags: Vec<Arc<AtomicBool>>,
+}
+
+impl Default for CancellationToken {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self {
+            flags: vec![Arc::new(AtomicBool::new(false))]
+        }
+    }
+
+    pub fn child(&self) -> Self {
+        let mut flags = vec![Arc::new(AtomicBool::new(false))];
+        flags.extend(self.flags.iter().cloned());
+
+        Self { flags }
+    }
+
+    pub fn cancel(&self) {
+        self.flags[0].store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.flags.iter().any(|flag| flag.load(Ordering::Relaxed))
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum SearchOutcome<T> {
+    Found(T),
+    // Every sequence of moves was tried, so there is no solution at all
+    Unsolvable,
+    BudgetExhausted,
+}
+
+impl<T> SearchOutcome<T> {
+    pub fn found(self) -> Option<T> {
+        match self {
+            SearchOutcome::Found(value) => Some(value),
+            _ => None
+        }
+    }
+
+    pub fn is_exhausted(&self) -> bool {
+        matches!(self, SearchOutcome::BudgetExhausted)
+    }
+
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> SearchOutcome<U> {
+        match self {
+            SearchOutcome::Found(value) => SearchOutcome::Found(f(value)),
+            SearchOutcome::Unsolvable => SearchOutcome::Unsolvable,
+            SearchOutcome::BudgetExhausted => SearchOutcome::BudgetExhausted
+        }
+    }
+}
@@ -1,19 +1,144 @@
 use std::env;
+use std::time::Duration;
 
 use cube::cube::Cube;
 use cube::algorithm::Algorithm;
-use cube::solver::Solver;
+use cube::analysis::DrCase;
+use cube::cfop::CfopSolver;
+use cube::kociemba::TwoPhaseSolver;
+use cube::leave::Leave;
+use cube::metric::Metric;
+use cube::observer::SolverObserver;
+use cube::optimal::OptimalSolver;
+use cube::roux::RouxSolver;
+use cube::solver::{ Solver, Threads };
+use cube::zz::{ ZzLastLayer, ZzSolver, ZzStart };
 
+// Prints every finished stage on stderr when running with --verbose
+struct StageLog;
+
+impl SolverObserver for StageLog {
+    fn stage_finished(&self, stage: &str, nodes: u64, elapsed: Duration) {
+        eprintln!("{stage}: {nodes} nodes in {:.3}s", elapsed.as_secs_f64());
+    }
+}
+
+// Generating the pruning tables takes minutes in debug builds, so run the solver with
+// `cargo run --release -- <scramble>`
 fn main() {
-    let alg = env::args().skip(1).next().unwrap();
+    let alg = env::args().nth(1).unwrap();
     println!("Solving scramble: {alg}");
 
     let mut cube = Cube::new();
     let alg = Algorithm::from_str(&alg[..]).unwrap();
     cube.execute_mut(&alg);
 
-    let solver = Solver::new();
-    let (alg, _) = solver.solve(&cube).unwrap();
+    // --metric=qtm and so on, HTM by default
+    let metric = env::args()
+        .filter_map(|arg| arg.strip_prefix("--metric=").map(str::to_uppercase))
+        .find_map(|name| Metric::ALL.into_iter().find(|metric| metric.to_string() == name))
+        .unwrap_or_default();
+
+    if env::args().any(|arg| arg == "--optimal") {
+        let alg = OptimalSolver::new()
+            .with_threads(Threads::available())
+            .with_metric(metric)
+            .solve(&cube)
+            .found()
+            .unwrap();
+        println!("{} ({} {}, optimal)", alg.to_string(), alg.length(metric), metric);
+        return;
+    }
+
+    if env::args().any(|arg| arg == "--two-phase") {
+        let alg = TwoPhaseSolver::new().solve(&cube).unwrap();
+        println!("{} ({} moves, two-phase)", alg.to_string(), alg.len());
+        return;
+    }
+
+    if env::args().any(|arg| arg == "--cfop") {
+        let solution = CfopSolver::new().with_metric(metric).solve(&cube).unwrap();
+
+        for step in &solution.steps {
+            println!("{}: {}", step.name, step);
+        }
+
+        println!(
+            "{} (cross on {}, {}, {}, {} {})",
+            solution.algorithm.to_string(),
+            solution.face,
+            solution.oll,
+            solution.pll,
+            solution.algorithm.length(metric),
+            metric
+        );
+        return;
+    }
+
+    if env::args().any(|arg| arg == "--roux") {
+        let solution = RouxSolver::new().with_metric(metric).solve(&cube).unwrap();
+
+        for step in &solution.steps {
+            println!("{}: {}", step.name, step);
+        }
+
+        println!("{} ({} {}, Roux)", solution.algorithm.to_string(), solution.algorithm.length(metric), metric);
+        return;
+    }
+
+    // --zz, with --eo-cross to start with EOCross rather than EOLine and --zbll to finish
+    // with ZBLL rather than OCLL and PLL
+    if env::args().any(|arg| arg == "--zz") {
+        let start = if env::args().any(|arg| arg == "--eo-cross") { ZzStart::EoCross } else { ZzStart::EoLine };
+        let last_layer = if env::args().any(|arg| arg == "--zbll") { ZzLastLayer::Zbll } else { ZzLastLayer::OcllPll };
+        let solution = ZzSolver::new().with_start(start).with_last_layer(last_layer).with_metric(metric).solve(&cube).unwrap();
+
+        for step in &solution.steps {
+            println!("{}: {}", step.name, step);
+        }
+
+        println!("{} ({} {}, ZZ)", solution.algorithm.to_string(), solution.algorithm.length(metric), metric);
+        return;
+    }
+
+    // --dr-cases=4c4e,4c2e to set up one of those cases before DR
+    let dr_cases: Vec<DrCase> = env::args()
+        .filter_map(|arg| arg.strip_prefix("--dr-cases=").map(String::from))
+        .flat_map(|cases| cases.split(',').map(|case| case.parse::<DrCase>().unwrap()).collect::<Vec<_>>())
+        .collect();
+
+    // --leave-corners=3 and --leave-edges=3 to stop at a skeleton leaving that many
+    let leave_arg = |name: &str| env::args()
+        .find_map(|arg| arg.strip_prefix(name).map(|count| count.parse::<usize>().unwrap()))
+        .unwrap_or(0);
+    let leave = Leave { corners: leave_arg("--leave-corners="), edges: leave_arg("--leave-edges=") };
+
+    let mut solver = Solver::new()
+        .with_metric(metric)
+        .with_dr_cases(dr_cases)
+        .with_leave(leave)
+        .with_threads(Threads::available());
+
+    if env::args().any(|arg| arg == "--verbose") {
+        solver = solver.with_observer(StageLog);
+    }
+
+    let solution = solver.solve(&cube).unwrap();
+
+    for step in &solution.steps {
+        println!("{}: {}", step.name, step);
+    }
+
+    println!(
+        "{} (EO {}, DR {}, {} {})",
+        solution.algorithm.to_string(),
+        solution.eo_axis,
+        solution.dr_axis,
+        solution.algorithm.length(metric),
+        metric
+    );
 
-    println!("{}", alg.to_string());
+    for cycle in &solution.cycles {
+        println!("Left: {}", cycle);
+    }
 }
@@ -0,0 +1,87 @@
+use std::fmt;
+
+use crate::algorithm::Move;
+
+// How moves are counted. Searches minimise the cost in their metric and
+// Algorithm::length reports it.
+//
+// HTM: face turns count one, slices count as the two face turns they are made of.
+// QTM: like HTM, but half turns count as two quarter turns.
+// STM: face and slice turns count one. Since solvers keep the centers fixed, a pair of
+//      face turns that moves the pieces like a slice (e.g. R L') also counts one.
+// ETM: every move counts one, rotations included.
+//
+// Rotations are free in every metric but ETM.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum Metric {
+    #[default]
+    HTM,
+    QTM,
+    STM,
+    ETM,
+}
+
+impl Metric {
+    pub const ALL: [Metric; 4] = [Metric::HTM, Metric::QTM, Metric::STM, Metric::ETM];
+
+    // What `mov` adds to the cost of an algorithm made of `previous` so far
+    pub fn cost(self, previous: &[Move], mov: Move) -> usize {
+        if mov.is_rotation() {
+            return usize::from(self == Metric::ETM);
+        }
+
+        let quarters = if mov.is_slice() { 2 * mov.amount() } else { mov.amount() };
+
+        match self {
+            Metric::HTM if mov.is_slice() => 2,
+            Metric::HTM | Metric::ETM => 1,
+            Metric::QTM => quarters,
+            Metric::STM => usize::from(!Self::completes_slice(previous, mov))
+        }
+    }
+
+    // A lower bound in this metric from a lower bound in face turns (HTM, no slices)
+    pub fn from_htm_bound(self, bound: usize) -> usize {
+        match self {
+            Metric::STM => bound.div_ceil(2),
+            _ => bound
+        }
+    }
+
+    // Whether `mov` pairs up with the previous move into a slice, which only counts if
+    // that move wasn't part of a pair already
+    fn completes_slice(previous: &[Move], mov: Move) -> bool {
+        let run = previous
+            .iter()
+            .rev()
+            .take_while(|other| other.is_face_turn() && other.axis() == mov.axis())
+            .count();
+
+        run == 1 && mov.forms_slice_with(previous[previous.len() - 1])
+    }
+}
+
+impl fmt::Display for Metric {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Metric::HTM => "HTM",
+            Metric::QTM => "QTM",
+            Metric::STM => "STM",
+            Metric::ETM => "ETM"
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Metric;
+    use crate::algorithm::Algorithm;
+
+    #[test]
+    fn counts_in_every_metric() {
+        let alg = Algorithm::from_str("R U2 M' x L R' F2 B2 E").unwrap();
+        let lengths = Metric::ALL.map(|metric| alg.length(metric));
+
+        assert_eq!(lengths, [10, 13, 6, 9]);
+    }
+}
@@ -0,0 +1,92 @@
+use std::sync::Arc;
+use std::time::{ Duration, Instant };
+
+use crate::algorithm::Algorithm;
+
+// Receives progress events while a solver runs. Every method does nothing by default,
+// so an observer only implements the events it cares about. `nodes` counts the states
+// visited by the stage so far and `elapsed` is the time since the stage started.
+pub trait SolverObserver: Send + Sync {
+    fn stage_started(&self, _stage: &str) {}
+
+    // IDA* starts another iteration, allowing solutions of `depth` moves
+    fn depth_increased(&self, _stage: &str, _depth: usize, _nodes: u64, _elapsed: Duration) {}
+
+    fn solution_found(&self, _stage: &str, _algorithm: &Algorithm, _nodes: u64, _elapsed: Duration) {}
+
+    fn stage_finished(&self, _stage: &str, _nodes: u64, _elapsed: Duration) {}
+}
+
+// Node count and start time of one stage, forwarded to the observer if there is one
+pub(crate) struct Progress {
+    stage: &'static str,
+    observer: Option<Arc<dyn SolverObserver>>,
+    started: Instant,
+    nodes: u64,
+    finished: bool,
+}
+
+impl Progress {
+    pub(crate) fn new(stage: &'static str, observer: Option<Arc<dyn SolverObserver>>) -> Self {
+        if let Some(observer) = &observer {
+            observer.stage_started(stage);
+        }
+
+        Self {
+            stage,
+            observer,
+            started: Instant::now(),
+            nodes: 0,
+            finished: false
+        }
+    }
+
+    pub(crate) fn unobserved() -> Self {
+        Self::new("", None)
+    }
+
+    // Counts one more visited state and returns the total so far
+    pub(crate) fn node(&mut self) -> u64 {
+        self.nodes += 1;
+        self.nodes
+    }
+
+    // Nodes visited on other threads for the same stage
+    pub(crate) fn add_nodes(&mut self, nodes: u64) {
+        self.nodes += nodes;
+    }
+
+    pub(crate) fn nodes(&self) -> u64 {
+        self.nodes
+    }
+
+    pub(crate) fn depth(&self, depth: usize) {
+        if let Some(observer) = &self.observer {
+            observer.depth_increased(self.stage, depth, self.nodes, self.started.elapsed());
+        }
+    }
+
+    pub(crate) fn solution(&self, alg: &Algorithm) {
+        if let Some(observer) = &self.observer {
+            observer.solution_found(self.stage, alg, self.nodes, self.started.elapsed());
+        }
+    }
+
+    pub(crate) fn finish(&mut self) {
+        if self.finished {
+            return;
+        }
+
+        self.finished = true;
+
+        if let Some(observer) = &self.observer {
+            observer.stage_finished(self.stage, self.nodes, self.started.elapsed());
+        }
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
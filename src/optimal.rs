@@ -0,0 +1,387 @@
+use std::array;
+use std::sync::{ Arc, OnceLock };
+
+use crate::algorithm::{ Algorithm, Move };
+use crate::coords::{ self, MoveTables, CO_SIZE, CP_SIZE };
+use crate::cube::Cube;
+use crate::limits::{ Limits, SearchOutcome };
+use crate::metric::Metric;
+use crate::observer::{ Progress, SolverObserver };
+use crate::permutation::{ EdgeLoc, Permutation };
+use crate::solver::{ SearchState, Solver, Threads };
+
+// Ordered positions of six edges out of twelve, times their orientations
+const EDGE_POSITIONS_SIZE: usize = 12 * 11 * 10 * 9 * 8 * 7;
+const EDGE_GROUP_SIZE: usize = EDGE_POSITIONS_SIZE * 64;
+const CORNERS_SIZE: usize = CP_SIZE * CO_SIZE;
+
+// The second group is the first one seen after an x2 rotation
+const EDGE_GROUP: [usize; 6] = [
+    EdgeLoc::UR as usize, EdgeLoc::UF as usize, EdgeLoc::UL as usize,
+    EdgeLoc::UB as usize, EdgeLoc::FR as usize, EdgeLoc::FL as usize
+];
+
+// Korf's pattern databases: one for all eight corners and two for six edges each,
+// stored as 4-bit distances. Any of them is a lower bound on the optimal solution.
+pub struct OptimalTables {
+    corners: Nibbles,
+    edges: Nibbles,
+    edge_moves: Vec<u32>,
+    rotated_moves: [Move; 18],
+}
+
+impl OptimalTables {
+    pub fn get() -> &'static Self {
+        static TABLES: OnceLock<OptimalTables> = OnceLock::new();
+        TABLES.get_or_init(Self::generate)
+    }
+
+    fn generate() -> Self {
+        let tables = MoveTables::get();
+        let edge_moves = edge_move_table();
+        let rotation = Permutation::X + Permutation::X;
+
+        let corners = Nibbles::distance_table(CORNERS_SIZE, 0, |i, mov| {
+            let (cp, co) = ((i / CO_SIZE) as u16, (i % CO_SIZE) as u16);
+            tables.cp.apply(cp, mov) as usize * CO_SIZE + tables.co.apply(co, mov) as usize
+        });
+
+        let edges = Nibbles::distance_table(EDGE_GROUP_SIZE, edge_coord(&Permutation::ID), |i, mov| {
+            apply_edges(&edge_moves, i as u32, mov) as usize
+        });
+
+        Self {
+            corners,
+            edges,
+            edge_moves,
+            rotated_moves: Move::MOVES_LIST.map(|mov| mov.rotate(rotation))
+        }
+    }
+
+    pub fn lower_bound(&self, state: OptimalState) -> usize {
+        let corners = self.corners.get(state.cp as usize * CO_SIZE + state.co as usize);
+        let edges = state.edges.map(|edges| self.edges.get(edges as usize));
+
+        corners.max(edges[0]).max(edges[1]) as usize
+    }
+}
+
+// Corners as permutation and orientation coordinates, and both edge groups
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct OptimalState {
+    pub cp: u16,
+    pub co: u16,
+    pub edges: [u32; 2],
+}
+
+impl OptimalState {
+    pub fn from_perm(perm: &Permutation) -> Self {
+        let rotation = Permutation::X + Permutation::X;
+
+        Self {
+            cp: coords::cp_coord(perm) as u16,
+            co: coords::co_coord(perm) as u16,
+            edges: [edge_coord(perm) as u32, edge_coord(&perm.conjugate(rotation)) as u32]
+        }
+    }
+}
+
+impl SearchState for OptimalState {
+    fn apply(self, mov: Move) -> Self {
+        let moves = MoveTables::get();
+        let tables = OptimalTables::get();
+
+        Self {
+            cp: moves.cp.apply(self.cp, mov),
+            co: moves.co.apply(self.co, mov),
+            edges: [
+                apply_edges(&tables.edge_moves, self.edges[0], mov),
+                apply_edges(&tables.edge_moves, self.edges[1], tables.rotated_moves[mov.index()])
+            ]
+        }
+    }
+}
+
+// IDA* on the whole cube with the pattern databases as heuristic, giving solutions
+// that are optimal in the half-turn metric
+pub struct OptimalSolver {
+    observer: Option<Arc<dyn SolverObserver>>,
+    limits: Limits,
+    threads: Threads,
+    metric: Metric,
+}
+
+impl Default for OptimalSolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OptimalSolver {
+    pub fn new() -> Self {
+        Self {
+            observer: None,
+            limits: Limits::default(),
+            threads: Threads::default(),
+            metric: Metric::default()
+        }
+    }
+
+    pub fn with_observer(mut self, observer: impl SolverObserver + 'static) -> Self {
+        self.observer = Some(Arc::new(observer));
+        self
+    }
+
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn with_threads(mut self, threads: Threads) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    // Solutions are optimal in `metric`. They only use face turns, so in STM a slice
+    // shows up as the pair of face turns it is equivalent to.
+    pub fn with_metric(mut self, metric: Metric) -> Self {
+        self.metric = metric;
+        self
+    }
+
+    pub fn solve(&self, cube: &Cube) -> SearchOutcome<Algorithm> {
+        let tables = OptimalTables::get();
+        let solved = OptimalState::from_perm(&Permutation::ID);
+        let metric = self.metric;
+
+        Solver::search_with_progress(
+            OptimalState::from_perm(&cube.state.normalize()),
+            &Vec::from(Move::MOVES_LIST),
+            move |state| state == solved,
+            move |state| metric.from_htm_bound(tables.lower_bound(state)),
+            metric,
+            &self.limits,
+            self.threads,
+            &mut Progress::new("Optimal", self.observer.clone())
+        ).map(|(alg, _)| alg)
+    }
+
+    // Every solution of at most `max_length` in the solver's metric, shortest first.
+    // Enumerating runs on a single thread.
+    pub fn solutions(&self, cube: &Cube, max_length: usize) -> impl Iterator<Item = Algorithm> {
+        let tables = OptimalTables::get();
+        let solved = OptimalState::from_perm(&Permutation::ID);
+        let metric = self.metric;
+
+        Solver::search_all(
+            OptimalState::from_perm(&cube.state.normalize()),
+            &Move::MOVES_LIST,
+            move |state| state == solved,
+            move |state| metric.from_htm_bound(tables.lower_bound(state)),
+            max_length
+        )
+            .observe("Optimal", self.observer.clone())
+            .with_limits(self.limits.clone())
+            .with_metric(metric)
+            .map(|(alg, _)| alg)
+    }
+
+    pub fn lower_bound(&self, cube: &Cube) -> usize {
+        let bound = OptimalTables::get().lower_bound(OptimalState::from_perm(&cube.state.normalize()));
+        self.metric.from_htm_bound(bound)
+    }
+}
+
+struct Nibbles {
+    data: Vec<u8>,
+}
+
+impl Nibbles {
+    const UNVISITED: u8 = 0xf;
+
+    fn get(&self, i: usize) -> u8 {
+        (self.data[i / 2] >> (i % 2 * 4)) & 0xf
+    }
+
+    fn set(&mut self, i: usize, value: u8) {
+        let byte = &mut self.data[i / 2];
+        *byte = (*byte & !(0xf << (i % 2 * 4))) | (value << (i % 2 * 4));
+    }
+
+    // Breadth-first search from the solved index, one full scan of the table per depth
+    // since a frontier list would be as large as the table itself. Once most entries are
+    // visited it is cheaper to look for unvisited entries next to the last layer instead.
+    fn distance_table(size: usize, solved: usize, next: impl Fn(usize, Move) -> usize) -> Self {
+        let mut table = Self { data: vec![0xff; size.div_ceil(2)] };
+        let mut depth = 0;
+        let mut visited = 1;
+        let mut found = true;
+
+        table.set(solved, 0);
+
+        while found {
+            found = false;
+
+            let backward = visited > size / 2;
+
+            for i in 0..size {
+                if backward {
+                    if table.get(i) == Self::UNVISITED && Move::MOVES_LIST.iter().any(|&mov| table.get(next(i, mov)) == depth) {
+                        table.set(i, depth + 1);
+                        visited += 1;
+                        found = true;
+                    }
+
+                    continue;
+                }
+
+                if table.get(i) != depth {
+                    continue;
+                }
+
+                for mov in Move::MOVES_LIST {
+                    let j = next(i, mov);
+
+                    if table.get(j) == Self::UNVISITED {
+                        table.set(j, depth + 1);
+                        visited += 1;
+                        found = true;
+                    }
+                }
+            }
+
+            depth += 1;
+        }
+
+        table
+    }
+}
+
+fn edge_coord(perm: &Permutation) -> usize {
+    let mut positions = [0; 6];
+    let mut orientation = 0;
+
+    for (loc, &piece) in perm.ep.iter().enumerate() {
+        if let Some(i) = EDGE_GROUP.iter().position(|&e| e == piece) {
+            positions[i] = loc;
+            orientation |= perm.eo[loc] << i;
+        }
+    }
+
+    positions_rank(&positions) * 64 + orientation
+}
+
+// Each entry packs the new positions rank with a mask of the edges that got flipped
+fn edge_move_table() -> Vec<u32> {
+    let mut table = Vec::with_capacity(EDGE_POSITIONS_SIZE * Move::MOVES_LIST.len());
+    let targets = Move::MOVES_LIST.map(|mov| {
+        let perm = mov.execute(Permutation::ID);
+        let mut target = [0; 12];
+
+        for i in 0..12 {
+            target[perm.ep[i]] = i;
+        }
+
+        (target, perm.eo)
+    });
+
+    for i in 0..EDGE_POSITIONS_SIZE {
+        let positions = positions_unrank(i);
+
+        for (target, flips) in &targets {
+            let moved = positions.map(|p| target[p]);
+            let mask = (0..6).fold(0, |acc, j| acc | (flips[moved[j]] << j));
+            table.push((positions_rank(&moved) | (mask << 20)) as u32);
+        }
+    }
+
+    table
+}
+
+fn apply_edges(table: &[u32], coord: u32, mov: Move) -> u32 {
+    let entry = table[(coord / 64) as usize * Move::MOVES_LIST.len() + mov.index()];
+    (entry & 0xfffff) * 64 + ((coord % 64) ^ (entry >> 20))
+}
+
+fn positions_rank(positions: &[usize; 6]) -> usize {
+    (0..6).fold(0, |acc, i| {
+        let smaller = positions[i] - positions[..i].iter().filter(|&&p| p < positions[i]).count();
+        acc * (12 - i) + smaller
+    })
+}
+
+fn positions_unrank(rank: usize) -> [usize; 6] {
+    let mut digits = [0; 6];
+    let mut rank = rank;
+
+    for i in (0..6).rev() {
+        digits[i] = rank % (12 - i);
+        rank /= 12 - i;
+    }
+
+    let mut remaining: Vec<usize> = (0..12).collect();
+    array::from_fn(|i| remaining.remove(digits[i]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn positions_round_trip() {
+        for i in [0, 1, 5000, 123456, EDGE_POSITIONS_SIZE - 1] {
+            assert_eq!(positions_rank(&positions_unrank(i)), i);
+        }
+    }
+
+    #[test]
+    fn stepping_matches_permutation() {
+        let alg = Algorithm::from_str("R U2 F' L D' B2 R' U F2 D L'").unwrap();
+        let state = alg.moves().iter().fold(OptimalState::from_perm(&Permutation::ID), |state, &mov| state.apply(mov));
+        assert_eq!(state, OptimalState::from_perm(&alg.execute(Permutation::ID)));
+    }
+
+    #[test]
+    fn finds_optimal_solution() {
+        let scramble = Algorithm::from_str("R U F' L2 D B' R2").unwrap();
+        let cube = Cube::new().execute(&scramble);
+        let solution = OptimalSolver::new().solve(&cube).found().unwrap();
+
+        assert_eq!(solution.execute(cube.state), Permutation::ID);
+        assert_eq!(solution.len(), 7);
+    }
+
+    #[test]
+    fn threads_find_an_optimal_solution() {
+        let scramble = Algorithm::from_str("R U F' L2 D B' R2").unwrap();
+        let cube = Cube::new().execute(&scramble);
+        let solution = OptimalSolver::new().with_threads(Threads { count: 4, deterministic: false }).solve(&cube).found().unwrap();
+
+        assert_eq!(solution.execute(cube.state), Permutation::ID);
+        assert_eq!(solution.len(), 7);
+    }
+
+    #[test]
+    fn finds_optimal_solution_in_other_metrics() {
+        let scramble = Algorithm::from_str("R L' U2 F").unwrap();
+        let cube = Cube::new().execute(&scramble);
+        let solve = |metric| OptimalSolver::new().with_metric(metric).solve(&cube).found().unwrap();
+
+        let qtm = solve(Metric::QTM);
+        assert_eq!(qtm.execute(cube.state), Permutation::ID);
+        assert_eq!(qtm.length(Metric::QTM), 5);
+
+        let stm = solve(Metric::STM);
+        assert_eq!(stm.execute(cube.state), Permutation::ID);
+        assert_eq!(stm.length(Metric::STM), 3);
+    }
+
+    #[test]
+    fn gives_up_when_out_of_budget() {
+        let scramble = Algorithm::from_str("R U F' L2 D B' R2").unwrap();
+        let cube = Cube::new().execute(&scramble);
+        let limits = Limits { max_depth: Some(6), ..Limits::default() };
+
+        assert!(OptimalSolver::new().with_limits(limits).solve(&cube).is_exhausted());
+    }
+}
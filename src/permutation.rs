@@ -96,12 +96,15 @@ impl EdgeLoc {
 
 // co[UFR] is the orientation of the piece currently in UFR
 // cp[UFR] is the original position index of the piece currently in UFR
-#[derive(Clone, Copy, Debug, PartialEq)]
+// centers[U] is the original face of the center currently on U, in the order
+// U, D, L, R, F, B. Only slice moves and rotations move them.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub struct Permutation {
 pub    co: [usize; 8],
 pub    eo: [usize; 12],
 pub    cp: [usize; 8],
 pub    ep: [usize; 12],
+pub    centers: [usize; 6],
 }
 
 impl Permutation {
@@ -110,6 +113,7 @@ impl Permutation {
         cp: [0, 1, 2, 3, 4, 5, 6, 7],
         eo: [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
         ep: [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11],
+        centers: [0, 1, 2, 3, 4, 5],
     };
 
     pub const U: Self = Self {
@@ -117,6 +121,7 @@ impl Permutation {
         cp: [3, 0, 1, 2, 4, 5, 6, 7],
         eo: [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
         ep: [3, 0, 1, 2, 4, 5, 6, 7, 8, 9, 10, 11],
+        centers: [0, 1, 2, 3, 4, 5],
     };
 
     pub const D: Self = Self {
@@ -124,6 +129,7 @@ impl Permutation {
         cp: [0, 1, 2, 3, 5, 6, 7, 4],
         eo: [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
         ep: [0, 1, 2, 3, 4, 5, 6, 7, 9, 10, 11, 8],
+        centers: [0, 1, 2, 3, 4, 5],
     };
 
     pub const L: Self = Self {
@@ -131,6 +137,7 @@ impl Permutation {
         cp: [0, 2, 6, 3, 4, 1, 5, 7],
         eo: [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
         ep: [0, 1, 7, 3, 4, 5, 2, 10, 8, 9, 6, 11],
+        centers: [0, 1, 2, 3, 4, 5],
     };
 
     pub const R: Self = Self {
@@ -138,6 +145,7 @@ impl Permutation {
         cp: [4, 1, 2, 0, 7, 5, 6, 3],
         eo: [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
         ep: [5, 1, 2, 3, 0, 8, 6, 7, 4, 9, 10, 11],
+        centers: [0, 1, 2, 3, 4, 5],
     };
 
     pub const F: Self = Self {
@@ -145,6 +153,7 @@ impl Permutation {
         cp: [1, 5, 2, 3, 0, 4, 6, 7],
         eo: [0, 1, 0, 0, 0, 1, 1, 0, 0, 1, 0, 0],
         ep: [0, 6, 2, 3, 4, 1, 9, 7, 8, 5, 10, 11],
+        centers: [0, 1, 2, 3, 4, 5],
     };
 
     pub const B: Self = Self {
@@ -152,8 +161,94 @@ impl Permutation {
         cp: [0, 1, 3, 7, 4, 5, 2, 6],
         eo: [0, 0, 0, 1, 1, 0, 0, 1, 0, 0, 0, 1],
         ep: [0, 1, 2, 4, 11, 5, 6, 3, 8, 9, 10, 7],
+        centers: [0, 1, 2, 3, 4, 5],
     };
 
+    // Whole-cube rotations in the direction of R, U and F. Conjugating by one of them
+    // (-X + perm + X) gives the same state seen with the cube held differently.
+    pub const X: Self = Self {
+        co: [2, 1, 2, 1, 1, 2, 1, 2],
+        cp: [4, 5, 1, 0, 7, 6, 2, 3],
+        eo: [0, 1, 0, 1, 0, 0, 0, 0, 0, 1, 0, 1],
+        ep: [5, 9, 6, 1, 0, 8, 10, 2, 4, 11, 7, 3],
+        centers: [4, 5, 2, 3, 1, 0],
+    };
+
+    pub const Y: Self = Self {
+        co: [0, 0, 0, 0, 0, 0, 0, 0],
+        cp: [3, 0, 1, 2, 7, 4, 5, 6],
+        eo: [0, 0, 0, 0, 1, 1, 1, 1, 0, 0, 0, 0],
+        ep: [3, 0, 1, 2, 7, 4, 5, 6, 11, 8, 9, 10],
+        centers: [0, 1, 4, 5, 3, 2],
+    };
+
+    pub const Z: Self = Self {
+        co: [1, 2, 1, 2, 2, 1, 2, 1],
+        cp: [1, 5, 6, 2, 0, 4, 7, 3],
+        eo: [1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1],
+        ep: [2, 6, 10, 7, 3, 1, 9, 11, 0, 5, 8, 4],
+        centers: [2, 3, 1, 0, 4, 5],
+    };
+
+    // Middle slices, turning like L, D and F
+    pub const M: Self = Self {
+        co: [0, 0, 0, 0, 0, 0, 0, 0],
+        cp: [0, 1, 2, 3, 4, 5, 6, 7],
+        eo: [0, 1, 0, 1, 0, 0, 0, 0, 0, 1, 0, 1],
+        ep: [0, 3, 2, 11, 4, 5, 6, 7, 8, 1, 10, 9],
+        centers: [5, 4, 2, 3, 0, 1],
+    };
+
+    pub const E: Self = Self {
+        co: [0, 0, 0, 0, 0, 0, 0, 0],
+        cp: [0, 1, 2, 3, 4, 5, 6, 7],
+        eo: [0, 0, 0, 0, 1, 1, 1, 1, 0, 0, 0, 0],
+        ep: [0, 1, 2, 3, 5, 6, 7, 4, 8, 9, 10, 11],
+        centers: [0, 1, 5, 4, 2, 3],
+    };
+
+    pub const S: Self = Self {
+        co: [0, 0, 0, 0, 0, 0, 0, 0],
+        cp: [0, 1, 2, 3, 4, 5, 6, 7],
+        eo: [1, 0, 1, 0, 0, 0, 0, 0, 1, 0, 1, 0],
+        ep: [2, 1, 10, 3, 4, 5, 6, 7, 0, 9, 8, 11],
+        centers: [2, 3, 1, 0, 4, 5],
+    };
+
+    // All 24 rotations, starting with the identity
+    pub fn rotations() -> Vec<Self> {
+        let mut rotations = vec![Self::ID];
+        let mut i = 0;
+
+        while i < rotations.len() {
+            for rotation in [Self::X, Self::Y] {
+                let next = rotations[i] + rotation;
+
+                if !rotations.contains(&next) {
+                    rotations.push(next);
+                }
+            }
+
+            i += 1;
+        }
+
+        rotations
+    }
+
+    pub fn conjugate(self, by: Self) -> Self {
+        -by + self + by
+    }
+
+    // The same state with the whole cube rotated so that the centers are back in place.
+    // Coordinates and solvers only look at pieces relative to fixed centers.
+    pub fn normalize(self) -> Self {
+        Self::rotations()
+            .into_iter()
+            .map(|rotation| self + rotation)
+            .find(|perm| perm.centers == Self::ID.centers)
+            .unwrap()
+    }
+
     pub fn corner_op(&self, i: CornerLoc) -> Result<(usize, CornerLoc), &'static str> {
         Ok((self.co[i as usize], CornerLoc::from_usize(self.cp[i as usize])?))
     }
@@ -179,6 +274,10 @@ impl Neg for Permutation {
             new.ep[self.ep[i]] = i;
         }
 
+        for i in 0..6 {
+            new.centers[self.centers[i]] = i;
+        }
+
         new
     }
 }
@@ -199,6 +298,10 @@ impl Add for Permutation {
             new.ep[i] = self.ep[other.ep[i]];
         }
 
+        for i in 0..6 {
+            new.centers[i] = self.centers[other.centers[i]];
+        }
+
         new
     }
 }
@@ -240,6 +343,27 @@ mod tests {
         assert_eq!(Permutation::U + -Permutation::U, Permutation::ID);
     }
 
+    #[test]
+    fn there_are_24_rotations() {
+        assert_eq!(Permutation::rotations().len(), 24);
+    }
+
+    #[test]
+    fn rotating_moves_gives_moves() {
+        assert_eq!(Permutation::U.conjugate(Permutation::X), Permutation::B);
+        assert_eq!(Permutation::F.conjugate(Permutation::X), Permutation::U);
+        assert_eq!(Permutation::R.conjugate(Permutation::Y), Permutation::F);
+        assert_eq!(Permutation::L.conjugate(Permutation::Y), Permutation::B);
+    }
+
+    #[test]
+    fn slices_are_faces_and_rotations() {
+        assert_eq!(Permutation::M, -Permutation::X + Permutation::R + -Permutation::L);
+        assert_eq!(Permutation::E, -Permutation::Y + Permutation::U + -Permutation::D);
+        assert_eq!(Permutation::S, Permutation::Z + -Permutation::F + Permutation::B);
+        assert_eq!(Permutation::Z * 4, Permutation::ID);
+    }
+
     #[test]
     fn three_u_is_u_inv() {
         assert_eq!(Permutation::U + Permutation::U + Permutation::U, -Permutation::U);
@@ -0,0 +1,246 @@
+use std::sync::Arc;
+
+use crate::algorithm::Algorithm;
+use crate::cube::Cube;
+use crate::limits::Limits;
+use crate::metric::Metric;
+use crate::observer::SolverObserver;
+use crate::permutation::Permutation;
+use crate::solver::{ Side, Step, Threads };
+use crate::stage::{ Stage, StageSearch };
+
+const MAX_STAGE_LENGTH: usize = 20;
+
+// How many solutions of a stage are compared for cancellations with the previous ones
+const CANCELLATION_CANDIDATES: usize = 64;
+
+struct PipelineStage {
+    stage: Box<dyn Stage>,
+    branching: usize,
+}
+
+// A method as a sequence of stages, each solved from where the previous ones left the
+// cube. Every stage branches on its `branching` shortest solutions, on the normal
+// scramble and also on the inverse one when NISS is enabled, and the shortest
+// combination wins.
+pub struct Pipeline {
+    stages: Vec<PipelineStage>,
+    niss: bool,
+    cancellation: bool,
+    observer: Option<Arc<dyn SolverObserver>>,
+    limits: Limits,
+    metric: Metric,
+    threads: Threads,
+}
+
+impl Default for Pipeline {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Self {
+            stages: vec![],
+            niss: false,
+            cancellation: false,
+            observer: None,
+            limits: Limits::default(),
+            metric: Metric::default(),
+            threads: Threads::default()
+        }
+    }
+
+    pub fn stage(mut self, stage: impl Stage + 'static, branching: usize) -> Self {
+        self.stages.push(PipelineStage { stage: Box::new(stage), branching });
+        self
+    }
+
+    pub fn with_niss(mut self, niss: bool) -> Self {
+        self.niss = niss;
+        self
+    }
+
+    // Pick each stage knowing how the solution so far ends, preferring solutions that
+    // cancel into it
+    pub fn with_cancellation(mut self, cancellation: bool) -> Self {
+        self.cancellation = cancellation;
+        self
+    }
+
+    pub fn with_observer(self, observer: impl SolverObserver + 'static) -> Self {
+        self.with_shared_observer(Some(Arc::new(observer)))
+    }
+
+    pub(crate) fn with_shared_observer(mut self, observer: Option<Arc<dyn SolverObserver>>) -> Self {
+        self.observer = observer;
+        self
+    }
+
+    // Applies to each stage search on its own. Once a stage runs out of budget the
+    // pipeline carries on with the candidates it already has.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    // Each stage is solved optimally in `metric`, and the combination that is shortest
+    // in it wins
+    pub fn with_metric(mut self, metric: Metric) -> Self {
+        self.metric = metric;
+        self
+    }
+
+    // Follows the candidates of a stage on separate threads, starting with the first
+    // stage that branches. The steps found are the same as with one thread.
+    pub fn with_threads(mut self, threads: Threads) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    // The steps of the shortest solution found, or None if some stage has no solution
+    // within the limits. Steps are given for the cube held with its centers in place.
+    pub fn solve(&self, cube: &Cube) -> Option<Vec<Step>> {
+        let mut best = None;
+        self.solve_from(0, cube.state.normalize(), &mut vec![], &mut best, self.threads);
+        best.map(|(_, steps)| steps)
+    }
+
+    // All normal moves in order, followed by the inverse of all inverse moves, with
+    // the moves that meet between steps cancelled
+    pub fn linear(steps: &[Step]) -> Algorithm {
+        let mut normal = Algorithm::new();
+        let mut inverse = Algorithm::new();
+
+        for step in steps {
+            match step.side {
+                Side::Normal => normal.append(&step.algorithm),
+                Side::Inverse => inverse.append(&step.algorithm)
+            };
+        }
+
+        normal.append(&inverse.inverse());
+        normal.cancel()
+    }
+
+    // `state` is the scramble with the inverse moves found so far as premoves, so the
+    // normal side appends to it and the inverse side appends to its inverse
+    fn solve_from(&self, index: usize, state: Permutation, steps: &mut Vec<Step>, best: &mut Option<(usize, Vec<Step>)>, threads: Threads) {
+        let Some(PipelineStage { stage, branching }) = self.stages.get(index) else {
+            let length = Self::linear(steps).length(self.metric);
+
+            if best.as_ref().is_none_or(|(best, _)| length < *best) {
+                *best = Some((length, steps.clone()));
+            }
+
+            return;
+        };
+
+        let candidates = self.candidates(stage.as_ref(), *branching, steps, state);
+
+        if threads.count > 1 && candidates.len() > 1 {
+            let single = Threads { count: 1, ..threads };
+            let found = threads.map(&candidates, |(alg, side)| {
+                let mut steps = steps.clone();
+                let mut best = None;
+
+                steps.push(Step { name: stage.name(), algorithm: alg.clone(), side: *side });
+                self.solve_from(index + 1, side.advance(state, alg), &mut steps, &mut best, single);
+                best
+            });
+
+            // In candidate order, like a single thread would have seen them
+            for (length, steps) in found.into_iter().flatten() {
+                if best.as_ref().is_none_or(|(best, _)| length < *best) {
+                    *best = Some((length, steps));
+                }
+            }
+
+            return;
+        }
+
+        for (alg, side) in candidates {
+            let next = side.advance(state, &alg);
+
+            steps.push(Step { name: stage.name(), algorithm: alg, side });
+            self.solve_from(index + 1, next, steps, best, threads);
+            steps.pop();
+        }
+    }
+
+    // The `count` shortest solutions of a stage on each side. With cancellation,
+    // solutions up to one move longer than the shortest are also considered, and they
+    // are ranked by the length of the whole solution once joined to `steps`.
+    fn candidates(&self, stage: &dyn Stage, count: usize, steps: &[Step], state: Permutation) -> Vec<(Algorithm, Side)> {
+        let sides = if self.niss && stage.allows_niss() { vec![Side::Normal, Side::Inverse] } else { vec![Side::Normal] };
+        let search = StageSearch::new(MAX_STAGE_LENGTH, self.limits.clone(), self.observer.clone(), self.metric);
+        let mut candidates = vec![];
+
+        for side in sides {
+            let solutions = stage.solutions(side.start(state), &search);
+
+            if !self.cancellation {
+                candidates.extend(solutions.take(count).map(|alg| (alg, side)));
+                continue;
+            }
+
+            let mut shortest = None;
+            let mut solutions: Vec<Algorithm> = solutions
+                .take_while(|alg| alg.length(self.metric) <= *shortest.get_or_insert(alg.length(self.metric)) + 1)
+                .take(CANCELLATION_CANDIDATES)
+                .collect();
+
+            solutions.sort_by_cached_key(|alg| {
+                let mut steps = steps.to_vec();
+                steps.push(Step { name: stage.name(), algorithm: alg.clone(), side });
+                Self::linear(&steps).length(self.metric)
+            });
+
+            candidates.extend(solutions.into_iter().take(count).map(|alg| (alg, side)));
+        }
+
+        candidates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Pipeline;
+    use crate::algorithm::{ Algorithm, Axis, Move };
+    use crate::cube::Cube;
+    use crate::permutation::Permutation;
+    use crate::stage::{ EoStage, Stage };
+
+    // Brute force to the solved cube, only usable a few moves away from it
+    struct Finish;
+
+    impl Stage for Finish {
+        fn name(&self) -> &'static str {
+            "Finish"
+        }
+
+        fn moves(&self) -> Vec<Move> {
+            Vec::from(Move::MOVES_LIST)
+        }
+
+        fn is_solved(&self, perm: &Permutation) -> bool {
+            *perm == Permutation::ID
+        }
+    }
+
+    #[test]
+    fn runs_custom_stages() {
+        let scramble = Algorithm::from_str("R U F").unwrap();
+        let cube = Cube::new().execute(&scramble);
+        let steps = Pipeline::new()
+            .stage(EoStage { axis: Axis::FB }, 1)
+            .stage(Finish, 1)
+            .solve(&cube)
+            .unwrap();
+
+        assert_eq!(steps.iter().map(|step| step.name).collect::<Vec<_>>(), ["EO", "Finish"]);
+        assert_eq!(steps[0].algorithm.len(), 1);
+        assert_eq!(Pipeline::linear(&steps).execute(cube.state), Permutation::ID);
+    }
+}
@@ -0,0 +1,144 @@
+use std::sync::OnceLock;
+
+use crate::algorithm::Move;
+use crate::coords::{
+    DrCoord, EoCoord, HtCoord, HtrCoord, MoveTables,
+    CP_SIZE, EO_SIZE, CO_SIZE, SLICE_SIZE, HTR_CORNERS_SIZE, SPLIT_SIZE, HT_EDGES_SIZE
+};
+use crate::solver::SearchState;
+
+const UNVISITED: u8 = u8::MAX;
+
+// One distance table per stage of Solver::solve, indexed by coordinates of the
+// permutation. The EO, DR and HTR tables are exact; the half-turn finish keeps
+// separate corner and edge tables and uses the larger of the two.
+pub struct PruningTables {
+    eo: Vec<u8>,
+    dr: Vec<u8>,
+    htr: Vec<u8>,
+    ht_corners: Vec<u8>,
+    ht_edges: Vec<u8>,
+}
+
+impl PruningTables {
+    pub fn get() -> &'static Self {
+        static TABLES: OnceLock<PruningTables> = OnceLock::new();
+        TABLES.get_or_init(Self::generate)
+    }
+
+    pub fn eo(&self, coord: EoCoord) -> usize {
+        self.eo[coord.0 as usize] as usize
+    }
+
+    pub fn dr(&self, coord: DrCoord) -> usize {
+        self.dr[dr_index(coord)] as usize
+    }
+
+    pub fn htr(&self, coord: HtrCoord) -> usize {
+        self.htr[htr_index(coord)] as usize
+    }
+
+    pub fn finish(&self, coord: HtCoord) -> usize {
+        self.ht_corners[coord.corners as usize].max(self.ht_edges[coord.edges as usize]) as usize
+    }
+
+    fn generate() -> Self {
+        let tables = MoveTables::get();
+
+        let moves: Vec<Move> = Vec::from(Move::MOVES_LIST);
+        let eo = distance_table(EO_SIZE, &[EoCoord::SOLVED.0 as usize], &moves, |i, mov| {
+            tables.eo.apply(i as u16, mov) as usize
+        });
+
+        let moves: Vec<Move> = moves.into_iter().filter(|mov| mov.keeps_eo()).collect();
+        let dr = distance_table(CO_SIZE * SLICE_SIZE, &[dr_index(DrCoord::SOLVED)], &moves, |i, mov| {
+            let coord = DrCoord { co: (i / SLICE_SIZE) as u16, slice: (i % SLICE_SIZE) as u16 };
+            dr_index(coord.apply(mov))
+        });
+
+        let moves: Vec<Move> = moves.into_iter().filter(|mov| mov.keeps_domino()).collect();
+        let htr = distance_table(HTR_CORNERS_SIZE * SPLIT_SIZE, &[htr_index(HtrCoord::SOLVED)], &moves, |i, mov| {
+            let coord = HtrCoord { corners: (i / SPLIT_SIZE) as u16, split: (i % SPLIT_SIZE) as u16 };
+            htr_index(coord.apply(mov))
+        });
+
+        let moves: Vec<Move> = moves.into_iter().filter(|mov| mov.is_halfturn()).collect();
+        let ht_corners = distance_table(CP_SIZE, &[HtCoord::SOLVED.corners as usize], &moves, |i, mov| {
+            tables.cp.apply(i as u16, mov) as usize
+        });
+        let ht_edges = distance_table(HT_EDGES_SIZE, &[HtCoord::SOLVED.edges as usize], &moves, |i, mov| {
+            tables.ht_edges.apply(i as u16, mov) as usize
+        });
+
+        Self { eo, dr, htr, ht_corners, ht_edges }
+    }
+}
+
+fn dr_index(coord: DrCoord) -> usize {
+    coord.co as usize * SLICE_SIZE + coord.slice as usize
+}
+
+fn htr_index(coord: HtrCoord) -> usize {
+    coord.corners as usize * SPLIT_SIZE + coord.split as usize
+}
+
+// Breadth-first search over a coordinate, starting from the solved values
+pub(crate) fn distance_table(size: usize, solved: &[usize], moves: &[Move], next: impl Fn(usize, Move) -> usize) -> Vec<u8> {
+    let mut table = vec![UNVISITED; size];
+    let mut frontier = Vec::from(solved);
+    let mut depth = 0;
+
+    for &i in solved {
+        table[i] = 0;
+    }
+
+    while !frontier.is_empty() {
+        depth += 1;
+
+        let mut next_frontier = vec![];
+
+        for i in frontier {
+            for &mov in moves {
+                let j = next(i, mov);
+
+                if table[j] == UNVISITED {
+                    table[j] = depth;
+                    next_frontier.push(j);
+                }
+            }
+        }
+
+        frontier = next_frontier;
+    }
+
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithm::Algorithm;
+    use crate::permutation::Permutation;
+
+    #[test]
+    fn solved_is_zero_everywhere() {
+        let tables = PruningTables::get();
+        let perm = Permutation::ID;
+
+        assert_eq!(tables.eo(EoCoord::from_perm(&perm)), 0);
+        assert_eq!(tables.dr(DrCoord::from_perm(&perm)), 0);
+        assert_eq!(tables.htr(HtrCoord::from_perm(&perm)), 0);
+        assert_eq!(tables.finish(HtCoord::from_perm(&perm)), 0);
+    }
+
+    #[test]
+    fn single_moves_are_distance_one() {
+        let tables = PruningTables::get();
+        let perm = |alg: &str| Algorithm::from_str(alg).unwrap().execute(Permutation::ID);
+
+        assert_eq!(tables.eo(EoCoord::from_perm(&perm("F"))), 1);
+        assert_eq!(tables.dr(DrCoord::from_perm(&perm("R"))), 1);
+        assert_eq!(tables.htr(HtrCoord::from_perm(&perm("U"))), 1);
+        assert_eq!(tables.finish(HtCoord::from_perm(&perm("R2"))), 1);
+    }
+}
@@ -0,0 +1,527 @@
+use std::sync::{ Arc, OnceLock };
+
+use crate::algorithm::{ Algorithm, Move };
+use crate::coords::{ self, MoveTables, PieceMoves, CO_SIZE, CP_SIZE, PIECE_STATES };
+use crate::cube::Cube;
+use crate::limits::Limits;
+use crate::metric::Metric;
+use crate::observer::SolverObserver;
+use crate::permutation::{ CornerLoc, EdgeLoc, Permutation };
+use crate::pipeline::Pipeline;
+use crate::pruning::distance_table;
+use crate::solver::{ SearchState, Step };
+use crate::stage::{ Stage, StageSearch };
+
+pub(crate) const BLOCK_SIZE: usize = PIECE_STATES.pow(5);
+const CO_D_CORNERS_SIZE: usize = CO_SIZE << 12;
+
+// The six edges left for LSE, each in one of 6 places with 2 orientations, with the
+// M slice centers and the last layer corners each off by one of 4 turns
+const LSE_EDGES: [usize; 6] = [
+    EdgeLoc::UR as usize, EdgeLoc::UF as usize, EdgeLoc::UL as usize,
+    EdgeLoc::UB as usize, EdgeLoc::DF as usize, EdgeLoc::DB as usize
+];
+const LSE_SIZE: usize = 720 * 64 * 4 * 4;
+
+// LSE can take more than the 20 moves a stage gets by default once M counts as two
+const MAX_LSE_LENGTH: usize = 40;
+
+// More than any LSE takes, for states that M and U can't solve
+const UNSOLVABLE_LSE: usize = MAX_LSE_LENGTH + 1;
+
+const LSE_MOVES: [Move; 6] = [
+    Move::MOVES_LIST[0], Move::MOVES_LIST[1], Move::MOVES_LIST[2],
+    Move::SLICE_MOVES[0], Move::SLICE_MOVES[1], Move::SLICE_MOVES[2]
+];
+
+// A 1x2x3 block: its two D corners, its two E slice edges and its D edge
+pub(crate) struct Block {
+    corners: [usize; 2],
+    edges: [usize; 3],
+}
+
+pub(crate) const FIRST_BLOCK: Block = Block {
+    corners: [CornerLoc::DFL as usize, CornerLoc::DBL as usize],
+    edges: [EdgeLoc::FL as usize, EdgeLoc::BL as usize, EdgeLoc::DL as usize],
+};
+
+pub(crate) const SECOND_BLOCK: Block = Block {
+    corners: [CornerLoc::DFR as usize, CornerLoc::DBR as usize],
+    edges: [EdgeLoc::FR as usize, EdgeLoc::BR as usize, EdgeLoc::DR as usize],
+};
+
+// Where the pieces of a block are, see PieceMoves
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub(crate) struct BlockState {
+    corners: [u8; 2],
+    edges: [u8; 3],
+}
+
+impl BlockState {
+    pub(crate) fn of(perm: &Permutation, block: &Block) -> Self {
+        Self {
+            corners: block.corners.map(|piece| PieceMoves::corner_state(perm, piece)),
+            edges: block.edges.map(|piece| PieceMoves::edge_state(perm, piece))
+        }
+    }
+
+    pub(crate) fn solved(block: &Block) -> Self {
+        Self::of(&Permutation::ID, block)
+    }
+
+    pub(crate) fn index(self) -> usize {
+        self.corners.iter().chain(&self.edges).fold(0, |index, &piece| index * PIECE_STATES + piece as usize)
+    }
+
+    pub(crate) fn from_index(index: usize) -> Self {
+        let digit = |i: usize| (index / PIECE_STATES.pow(4 - i as u32) % PIECE_STATES) as u8;
+
+        Self {
+            corners: [digit(0), digit(1)],
+            edges: [digit(2), digit(3), digit(4)]
+        }
+    }
+}
+
+impl SearchState for BlockState {
+    fn apply(self, mov: Move) -> Self {
+        let pieces = PieceMoves::get();
+
+        Self {
+            corners: self.corners.map(|corner| pieces.corner(corner, mov)),
+            edges: self.edges.map(|edge| pieces.edge(edge, mov))
+        }
+    }
+}
+
+// Both blocks and the corners, which CMLL solves up to a U turn
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+struct CmllState {
+    first: BlockState,
+    second: BlockState,
+    cp: u16,
+    co: u16,
+}
+
+impl CmllState {
+    fn from_perm(perm: &Permutation) -> Self {
+        Self {
+            first: BlockState::of(perm, &FIRST_BLOCK),
+            second: BlockState::of(perm, &SECOND_BLOCK),
+            cp: coords::cp_coord(perm) as u16,
+            co: coords::co_coord(perm) as u16
+        }
+    }
+}
+
+impl CmllState {
+    // The locations of the D corners of both blocks
+    fn d_corners(&self) -> [usize; 4] {
+        let [a, b] = self.first.corners;
+        let [c, d] = self.second.corners;
+        [a, b, c, d].map(|corner| corner as usize / 3)
+    }
+}
+
+impl SearchState for CmllState {
+    fn apply(self, mov: Move) -> Self {
+        let tables = MoveTables::get();
+
+        Self {
+            first: self.first.apply(mov),
+            second: self.second.apply(mov),
+            cp: tables.cp.apply(self.cp, mov),
+            co: tables.co.apply(self.co, mov)
+        }
+    }
+}
+
+// The edges and centers LSE solves and how far the last layer is turned, as an index
+// into the LSE distance table
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+struct LseCoord(u32);
+
+impl LseCoord {
+    // None unless every other edge is solved, the centers are off by an M turn and the
+    // corners by a U turn, as only then M and U can solve the cube
+    fn from_perm(perm: &Permutation) -> Option<Self> {
+        let others_solved = (0..12)
+            .filter(|location| !LSE_EDGES.contains(location))
+            .all(|location| perm.ep[location] == location && perm.eo[location] == 0);
+
+        if !others_solved || perm.co != [0; 8] {
+            return None;
+        }
+
+        let order = LSE_EDGES.map(|location| LSE_EDGES.iter().position(|&piece| piece == perm.ep[location]).unwrap());
+        let flips = LSE_EDGES.iter().rev().fold(0, |bits, &location| bits * 2 + perm.eo[location]);
+        let centers = (0..4).find(|&turns| (Permutation::M * turns).centers == perm.centers)? as usize;
+        let corners = (0..4).find(|&turns| (Permutation::U * turns).cp == perm.cp)? as usize;
+
+        Some(Self((((coords::perm_rank(&order) * 64 + flips) * 4 + centers) * 4 + corners) as u32))
+    }
+
+    fn to_perm(self) -> Permutation {
+        let index = self.0 as usize;
+        let order: [usize; 6] = coords::perm_unrank(index / 1024);
+        let flips = index / 16 % 64;
+        let centers = Permutation::M * (index / 4 % 4) as isize;
+        let mut perm = Permutation { centers: centers.centers, ..Permutation::U * (index % 4) as isize };
+
+        for (i, &location) in LSE_EDGES.iter().enumerate() {
+            perm.ep[location] = LSE_EDGES[order[i]];
+            perm.eo[location] = flips >> i & 1;
+        }
+
+        perm
+    }
+}
+
+impl SearchState for LseCoord {
+    fn apply(self, mov: Move) -> Self {
+        Self(RouxTables::get().lse_moves[lse_move_index(self.0 as usize, mov)])
+    }
+}
+
+fn lse_move_index(coord: usize, mov: Move) -> usize {
+    coord * LSE_MOVES.len() + LSE_MOVES.iter().position(|&lse_move| lse_move == mov).unwrap()
+}
+
+// Distances in each stage's own moves. CMLL may take the blocks apart on the way, so it
+// is bounded by both blocks with any face turn, by the corner permutation and by the
+// corner orientation along with where the D corners are. LSE has its own move table, as
+// its coordinate doesn't fit the ones of MoveTables.
+struct RouxTables {
+    first_block: Vec<u8>,
+    second_block: Vec<u8>,
+    second_block_any: Vec<u8>,
+    cp: Vec<u8>,
+    co_d_corners: Vec<u8>,
+    lse_moves: Vec<u32>,
+    lse: Vec<u8>,
+}
+
+impl RouxTables {
+    fn get() -> &'static Self {
+        static TABLES: OnceLock<RouxTables> = OnceLock::new();
+        TABLES.get_or_init(Self::generate)
+    }
+
+    fn generate() -> Self {
+        let tables = MoveTables::get();
+        let pieces = PieceMoves::get();
+        let block = |block: &Block, moves: &[Move]| {
+            distance_table(BLOCK_SIZE, &[BlockState::solved(block).index()], moves, |i, mov| {
+                BlockState::from_index(i).apply(mov).index()
+            })
+        };
+        let auf: Vec<usize> = (0..4).map(|turns| coords::cp_coord(&(Permutation::U * turns))).collect();
+        let lse_moves: Vec<u32> = (0..LSE_SIZE as u32)
+            .flat_map(|i| {
+                let perm = LseCoord(i).to_perm();
+                LSE_MOVES.map(|mov| LseCoord::from_perm(&mov.execute(perm)).unwrap().0)
+            })
+            .collect();
+        let lse = distance_table(LSE_SIZE, &[LseCoord::from_perm(&Permutation::ID).unwrap().0 as usize], &LSE_MOVES, |i, mov| {
+            lse_moves[lse_move_index(i, mov)] as usize
+        });
+
+        Self {
+            first_block: block(&FIRST_BLOCK, &Move::MOVES_LIST),
+            second_block: block(&SECOND_BLOCK, &second_block_moves()),
+            second_block_any: block(&SECOND_BLOCK, &Move::MOVES_LIST),
+            cp: distance_table(CP_SIZE, &auf, &Move::MOVES_LIST, |i, mov| tables.cp.apply(i as u16, mov) as usize),
+            co_d_corners: distance_table(CO_D_CORNERS_SIZE, &[co_d_corners_index(0, CmllState::from_perm(&Permutation::ID).d_corners())], &Move::MOVES_LIST, |i, mov| {
+                let co = tables.co.apply((i >> 12) as u16, mov);
+                let locations = [3, 2, 1, 0].map(|digit| (i >> (3 * digit)) % 8);
+                co_d_corners_index(co, locations.map(|location| pieces.corner(location as u8 * 3, mov) as usize / 3))
+            }),
+            lse_moves,
+            lse
+        }
+    }
+
+    fn cmll(&self, state: CmllState) -> usize {
+        [
+            self.first_block[state.first.index()],
+            self.second_block_any[state.second.index()],
+            self.cp[state.cp as usize],
+            self.co_d_corners[co_d_corners_index(state.co, state.d_corners())]
+        ].into_iter().max().unwrap() as usize
+    }
+}
+
+// The orientation of every corner with the locations of the D corners, 3 bits each
+fn co_d_corners_index(co: u16, d_corners: [usize; 4]) -> usize {
+    d_corners.iter().fold(co as usize, |index, &location| index << 3 | location)
+}
+
+fn second_block_moves() -> Vec<Move> {
+    ["R", "R2", "R'", "U", "U2", "U'", "M", "M2", "M'"].map(|mov| Move::from_str(mov).unwrap()).into()
+}
+
+// The 1x2x3 block on the left, with any face turn
+pub struct FirstBlockStage;
+
+impl Stage for FirstBlockStage {
+    fn name(&self) -> &'static str {
+        "FB"
+    }
+
+    fn moves(&self) -> Vec<Move> {
+        Vec::from(Move::MOVES_LIST)
+    }
+
+    fn is_solved(&self, perm: &Permutation) -> bool {
+        BlockState::of(perm, &FIRST_BLOCK) == BlockState::solved(&FIRST_BLOCK)
+    }
+
+    fn heuristic(&self, perm: &Permutation) -> usize {
+        RouxTables::get().first_block[BlockState::of(perm, &FIRST_BLOCK).index()] as usize
+    }
+
+    fn allows_niss(&self) -> bool {
+        false
+    }
+
+    fn solutions<'a>(&'a self, state: Permutation, search: &StageSearch) -> Box<dyn Iterator<Item = Algorithm> + 'a> {
+        let tables = RouxTables::get();
+        let solved = BlockState::solved(&FIRST_BLOCK);
+
+        search.run(
+            self.name(),
+            BlockState::of(&state, &FIRST_BLOCK),
+            &self.moves(),
+            move |block| block == solved,
+            |block| tables.first_block[block.index()] as usize
+        )
+    }
+}
+
+// The 1x2x3 block on the right, with R, U and M so that the first one stays
+pub struct SecondBlockStage;
+
+impl Stage for SecondBlockStage {
+    fn name(&self) -> &'static str {
+        "SB"
+    }
+
+    fn moves(&self) -> Vec<Move> {
+        second_block_moves()
+    }
+
+    fn is_solved(&self, perm: &Permutation) -> bool {
+        BlockState::of(perm, &SECOND_BLOCK) == BlockState::solved(&SECOND_BLOCK)
+    }
+
+    fn heuristic(&self, perm: &Permutation) -> usize {
+        RouxTables::get().second_block[BlockState::of(perm, &SECOND_BLOCK).index()] as usize
+    }
+
+    fn allows_niss(&self) -> bool {
+        false
+    }
+
+    fn solutions<'a>(&'a self, state: Permutation, search: &StageSearch) -> Box<dyn Iterator<Item = Algorithm> + 'a> {
+        let tables = RouxTables::get();
+        let solved = BlockState::solved(&SECOND_BLOCK);
+
+        search.run(
+            self.name(),
+            BlockState::of(&state, &SECOND_BLOCK),
+            &self.moves(),
+            move |block| block == solved,
+            |block| tables.second_block[block.index()] as usize
+        )
+    }
+}
+
+// The last layer corners up to a U turn, with any face turn as long as both blocks are
+// back in place at the end. The M slice is left as it is.
+pub struct CmllStage;
+
+impl Stage for CmllStage {
+    fn name(&self) -> &'static str {
+        "CMLL"
+    }
+
+    fn moves(&self) -> Vec<Move> {
+        Vec::from(Move::MOVES_LIST)
+    }
+
+    fn is_solved(&self, perm: &Permutation) -> bool {
+        RouxTables::get().cmll(CmllState::from_perm(perm)) == 0
+    }
+
+    fn heuristic(&self, perm: &Permutation) -> usize {
+        RouxTables::get().cmll(CmllState::from_perm(perm))
+    }
+
+    fn allows_niss(&self) -> bool {
+        false
+    }
+
+    fn solutions<'a>(&'a self, state: Permutation, search: &StageSearch) -> Box<dyn Iterator<Item = Algorithm> + 'a> {
+        let tables = RouxTables::get();
+
+        search.run(
+            self.name(),
+            CmllState::from_perm(&state),
+            &self.moves(),
+            |state| tables.cmll(state) == 0,
+            |state| tables.cmll(state)
+        )
+    }
+}
+
+// The last six edges and the centers, with M and U
+pub struct LseStage;
+
+impl Stage for LseStage {
+    fn name(&self) -> &'static str {
+        "LSE"
+    }
+
+    fn moves(&self) -> Vec<Move> {
+        Vec::from(LSE_MOVES)
+    }
+
+    fn is_solved(&self, perm: &Permutation) -> bool {
+        *perm == Permutation::ID
+    }
+
+    fn heuristic(&self, perm: &Permutation) -> usize {
+        LseCoord::from_perm(perm).map_or(UNSOLVABLE_LSE, |coord| RouxTables::get().lse[coord.0 as usize] as usize)
+    }
+
+    fn allows_niss(&self) -> bool {
+        false
+    }
+
+    fn solutions<'a>(&'a self, state: Permutation, search: &StageSearch) -> Box<dyn Iterator<Item = Algorithm> + 'a> {
+        let tables = RouxTables::get();
+        let Some(coord) = LseCoord::from_perm(&state) else {
+            return Box::new(std::iter::empty());
+        };
+
+        search.with_max_length(MAX_LSE_LENGTH).run(
+            self.name(),
+            coord,
+            &self.moves(),
+            |coord| tables.lse[coord.0 as usize] == 0,
+            |coord| tables.lse[coord.0 as usize] as usize
+        )
+    }
+}
+
+pub struct RouxSolution {
+    pub algorithm: Algorithm,
+    // FB, SB, CMLL and LSE
+    pub steps: Vec<Step>,
+}
+
+// Solves with the Roux method, each stage optimally with its own moves: the first block
+// with any face turn, the second block with R, U and M, CMLL with any face turn and LSE
+// with M and U. The blocks are built on L and R with the last layer on U.
+pub struct RouxSolver {
+    observer: Option<Arc<dyn SolverObserver>>,
+    limits: Limits,
+    metric: Metric,
+}
+
+impl Default for RouxSolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RouxSolver {
+    pub fn new() -> Self {
+        Self {
+            observer: None,
+            limits: Limits::default(),
+            metric: Metric::default()
+        }
+    }
+
+    pub fn with_observer(mut self, observer: impl SolverObserver + 'static) -> Self {
+        self.observer = Some(Arc::new(observer));
+        self
+    }
+
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn with_metric(mut self, metric: Metric) -> Self {
+        self.metric = metric;
+        self
+    }
+
+    pub fn pipeline(&self) -> Pipeline {
+        Pipeline::new()
+            .stage(FirstBlockStage, 1)
+            .stage(SecondBlockStage, 1)
+            .stage(CmllStage, 1)
+            .stage(LseStage, 1)
+            .with_shared_observer(self.observer.clone())
+            .with_limits(self.limits.clone())
+            .with_metric(self.metric)
+    }
+
+    pub fn solve(&self, cube: &Cube) -> Option<RouxSolution> {
+        let steps = self.pipeline().solve(cube)?;
+        Some(RouxSolution { algorithm: Pipeline::linear(&steps), steps })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ LseCoord, LseStage, RouxSolver };
+    use crate::algorithm::Algorithm;
+    use crate::cube::Cube;
+    use crate::limits::Limits;
+    use crate::metric::Metric;
+    use crate::permutation::Permutation;
+    use crate::stage::{ Stage, StageSearch };
+
+    #[test]
+    fn solves_with_roux() {
+        let scramble = Algorithm::from_str("D2 B' R2 U2 F D2 L2 B' U2 F' R' D' B2 L' U' F2 R U L2").unwrap();
+        let cube = Cube::new().execute(&scramble);
+        let solution = RouxSolver::new().solve(&cube).unwrap();
+        let names = |step: usize| -> Vec<String> {
+            solution.steps[step].algorithm.moves().iter().map(|mov| mov.to_string()[..1].to_string()).collect()
+        };
+
+        assert_eq!(solution.steps.iter().map(|step| step.name).collect::<Vec<_>>(), ["FB", "SB", "CMLL", "LSE"]);
+        assert_eq!(solution.algorithm.execute(cube.state), Permutation::ID);
+        assert!(names(1).iter().all(|name| ["R", "U", "M"].contains(&&name[..])));
+        assert!(names(3).iter().all(|name| ["U", "M"].contains(&&name[..])));
+    }
+
+    #[test]
+    fn solves_lse_optimally() {
+        let state = Algorithm::from_str("M' U2 M U2").unwrap().execute(Permutation::ID);
+        let search = StageSearch::new(20, Limits::default(), None, Metric::STM);
+        let lse = LseStage.solutions(state, &search).next().unwrap();
+
+        assert_eq!(lse.length(Metric::STM), 4);
+        assert_eq!(lse.execute(state), Permutation::ID);
+        assert_eq!(LseCoord::from_perm(&state).unwrap().to_perm(), state);
+    }
+
+    #[test]
+    fn leaves_states_before_lse_alone() {
+        let search = StageSearch::new(20, Limits::default(), None, Metric::STM);
+
+        for scramble in ["R U2 F' L D' B2 R' U F2 D L'", "R U R' U R U2 R'", "R", "E"] {
+            let state = Algorithm::from_str(scramble).unwrap().execute(Permutation::ID);
+
+            assert!(LseCoord::from_perm(&state).is_none(), "{scramble}");
+            assert!(LseStage.heuristic(&state) > 20, "{scramble}");
+            assert!(LseStage.solutions(state, &search).next().is_none(), "{scramble}");
+        }
+    }
+}
@@ -1,133 +1,565 @@
-use crate::algorithm::{ Algorithm, Move };
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::fmt;
+use std::hash::Hash;
+use std::sync::{ Arc, Mutex };
+use std::sync::atomic::{ AtomicUsize, Ordering };
+use std::thread;
+
+use crate::algorithm::{ Algorithm, Axis, Move };
+use crate::analysis::{ DrCase, HtrSubset };
 use crate::cube::Cube;
-use crate::permutation::{ CornerLoc, EdgeLoc, Permutation };
+use crate::leave::{ Cycle, Leave };
+use crate::limits::{ CancellationToken, Limits, SearchOutcome };
+use crate::metric::Metric;
+use crate::observer::{ Progress, SolverObserver };
+use crate::permutation::Permutation;
+use crate::pipeline::Pipeline;
+use crate::stage::{ DrCaseStage, DrStage, EoStage, FinishStage, HtrStage, LeaveStage, SubsetDrStage };
+use crate::transposition::TranspositionTable;
+
+// Anything IDA* can step through with moves: whole permutations, or coordinates backed
+// by move tables when a stage only cares about part of the state. States are compared
+// and hashed to find them again in a transposition table.
+pub trait SearchState: Copy + Eq + Hash {
+    fn apply(self, mov: Move) -> Self;
+}
+
+impl SearchState for Permutation {
+    fn apply(self, mov: Move) -> Self {
+        mov.execute(self)
+    }
+}
+
+impl<A: SearchState, B: SearchState> SearchState for (A, B) {
+    fn apply(self, mov: Move) -> Self {
+        (self.0.apply(mov), self.1.apply(mov))
+    }
+}
+
+// Which scramble a step was found on. Inverse steps are written in parentheses and
+// end up inverted at the end of the linear solution.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Side {
+    Normal,
+    Inverse,
+}
+
+impl Side {
+    // `state` is the normal scramble with every inverse move so far as premoves
+    pub(crate) fn start(self, state: Permutation) -> Permutation {
+        match self {
+            Side::Normal => state,
+            Side::Inverse => -state
+        }
+    }
+
+    pub(crate) fn advance(self, state: Permutation, alg: &Algorithm) -> Permutation {
+        match self {
+            Side::Normal => alg.execute(state),
+            Side::Inverse => -alg.execute(-state)
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Step {
+    pub name: &'static str,
+    pub algorithm: Algorithm,
+    pub side: Side,
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.side {
+            Side::Normal => f.write_str(&self.algorithm.to_string()),
+            Side::Inverse => write!(f, "({})", self.algorithm.to_string())
+        }
+    }
+}
+
+pub struct Solution {
+    pub algorithm: Algorithm,
+    pub state: Permutation,
+    pub eo_axis: Axis,
+    pub dr_axis: Axis,
+    pub steps: Vec<Step>,
+    // What the solution leaves unsolved, empty unless the solver has a leave allowance
+    pub cycles: Vec<Cycle>,
+}
+
+// How many of the shortest solutions of each stage the solver tries before moving on
+// to the next stage. The half-turn finish is always solved optimally.
+#[derive(Clone, Copy, Debug)]
+pub struct Branching {
+    pub eo: usize,
+    pub dr: usize,
+    pub htr: usize,
+}
+
+impl Default for Branching {
+    fn default() -> Self {
+        Self { eo: 1, dr: 1, htr: 1 }
+    }
+}
+
+// How many threads IDA* uses, each taking the subtrees of root moves in turn. With
+// `deterministic` the result is the solution a single thread would find, the first by
+// length and then by move order; otherwise it is whichever shortest solution turns up
+// first, which saves waiting for the subtrees of earlier moves.
+#[derive(Clone, Copy, Debug)]
+pub struct Threads {
+    pub count: usize,
+    pub deterministic: bool,
+}
+
+impl Default for Threads {
+    fn default() -> Self {
+        Self { count: 1, deterministic: true }
+    }
+}
+
+impl Threads {
+    // One thread per core
+    pub fn available() -> Self {
+        Self {
+            count: thread::available_parallelism().map_or(1, |count| count.get()),
+            deterministic: true
+        }
+    }
+
+    // `job` for each of `items` on up to `count` threads, each taking the next item once
+    // it is free. The results come back in the order of the items.
+    pub(crate) fn map<T: Sync, R: Send>(self, items: &[T], job: impl Fn(&T) -> R + Sync) -> Vec<R> {
+        if self.count <= 1 || items.len() <= 1 {
+            return items.iter().map(job).collect();
+        }
+
+        let (next, results) = (&AtomicUsize::new(0), &Mutex::new(vec![]));
+        let job = &job;
+
+        thread::scope(|scope| {
+            for _ in 0..self.count.min(items.len()) {
+                scope.spawn(move || loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(item) = items.get(i) else {
+                        break;
+                    };
+
+                    let result = job(item);
+                    results.lock().unwrap().push((i, result));
+                });
+            }
+        });
+
+        let mut results = std::mem::take(&mut *results.lock().unwrap());
+        results.sort_by_key(|&(i, _)| i);
+        results.into_iter().map(|(_, result)| result).collect()
+    }
+}
 
 pub struct Solver {
-    
+    branching: Branching,
+    niss: bool,
+    cancellation: bool,
+    observer: Option<Arc<dyn SolverObserver>>,
+    limits: Limits,
+    metric: Metric,
+    dr_cases: Vec<DrCase>,
+    htr_subsets: Vec<HtrSubset>,
+    leave: Leave,
+    threads: Threads,
+}
+
+impl Default for Solver {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Solver {
     pub fn new() -> Self {
-        Self {}
+        Self {
+            branching: Branching::default(),
+            niss: false,
+            cancellation: false,
+            observer: None,
+            limits: Limits::default(),
+            metric: Metric::default(),
+            dr_cases: vec![],
+            htr_subsets: vec![],
+            leave: Leave::default(),
+            threads: Threads::default()
+        }
     }
 
-    pub fn solve(&self, cube: &Cube) -> Option<(Algorithm, Permutation)> {
-        let state = cube.state;
+    pub fn with_branching(mut self, branching: Branching) -> Self {
+        self.branching = branching;
+        self
+    }
 
-        let moves = Vec::from(Move::MOVES_LIST);
-        let (eo_stage, state) = Self::search(
-            state,
-            &moves,
-            |perm| perm.eo.iter().all(|&o| o == 0),
-            |_| 1
-        )?;
-        println!("EO: {}", eo_stage.to_string());
-
-        let moves = moves.into_iter().filter(|mov| mov.keeps_eo()).collect();
-        let (dr_stage, state) = Self::search(
-            state,
-            &moves,
-            |perm| {
-                if perm.co.iter().any(|&o| o != 0) {
-                    return false
-                }
+    pub fn with_niss(mut self, niss: bool) -> Self {
+        self.niss = niss;
+        self
+    }
 
-                let belt_edges = vec![
-                    EdgeLoc::BR as usize,
-                    EdgeLoc::FR as usize,
-                    EdgeLoc::FL as usize,
-                    EdgeLoc::BL as usize
-                ];
-
-                belt_edges.iter().all(|&e| belt_edges.contains(&perm.ep[e]))
-            },
-            |_| 1
-        )?;
-        println!("DR: {}", dr_stage.to_string());
-
-        let moves = moves.into_iter().filter(|mov| mov.keeps_domino()).collect();
-        let (ht_stage, state) = Self::search(
-            state,
-            &moves,
-            |perm| {
-                perm.cp.iter().all(|&p| CornerLoc::from_usize(p).unwrap().ht_class() == CornerLoc::from_usize(perm.cp[p]).unwrap().ht_class()) &&
-                perm.ep.iter().all(|&p| EdgeLoc::from_usize(p).unwrap().ht_class() == EdgeLoc::from_usize(perm.ep[p]).unwrap().ht_class())
-            },
-            |_| 1
-        )?;
-        println!("HT: {}", ht_stage.to_string());
-
-        let moves = moves.into_iter().filter(|mov| mov.is_halfturn()).collect();
-        let (solve_stage, state) = Self::search(
-            state,
-            &moves,
-            |perm| perm == Permutation::ID,
-            |_| 1
-        )?;
-        println!("Solve: {}", solve_stage.to_string());
+    // Pick each stage knowing how the solution so far ends, preferring solutions that
+    // cancel into it
+    pub fn with_cancellation(mut self, cancellation: bool) -> Self {
+        self.cancellation = cancellation;
+        self
+    }
 
-        let mut alg = Algorithm::new();
+    pub fn with_observer(mut self, observer: impl SolverObserver + 'static) -> Self {
+        self.observer = Some(Arc::new(observer));
+        self
+    }
+
+    // Applies to each stage search on its own. Once a stage runs out of budget the
+    // solver carries on with the candidates it already has, so `solve` still returns
+    // the best solution found in time, or None if there is none.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn with_metric(mut self, metric: Metric) -> Self {
+        self.metric = metric;
+        self
+    }
+
+    // Sets up one of `cases` after EO and finishes DR from there, the way FMC solvers
+    // pick their DR. With no cases, the default, DR is solved from wherever EO ends.
+    pub fn with_dr_cases(mut self, cases: Vec<DrCase>) -> Self {
+        self.dr_cases = cases;
+        self
+    }
+
+    // Prefers DRs that leave one of `subsets` for HTR, even at the cost of a longer DR
+    pub fn with_htr_subsets(mut self, subsets: Vec<HtrSubset>) -> Self {
+        self.htr_subsets = subsets;
+        self
+    }
+
+    // Stops at a skeleton once no more pieces are unsolved than `leave` allows, which
+    // is usually shorter than solving the cube. The solution lists the cycles left for
+    // insertions.
+    pub fn with_leave(mut self, leave: Leave) -> Self {
+        self.leave = leave;
+        self
+    }
+
+    // Runs the pipelines of the axis combinations side by side, and shares the threads
+    // left over among their stage candidates. The solution is the same as with one thread.
+    pub fn with_threads(mut self, threads: Threads) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    // The built-in method with EO on `eo_axis` and DR on `dr_axis`, configured like
+    // this solver
+    pub fn pipeline(&self, eo_axis: Axis, dr_axis: Axis) -> Pipeline {
+        let mut pipeline = Pipeline::new().stage(EoStage { axis: eo_axis }, self.branching.eo);
+
+        if !self.dr_cases.is_empty() {
+            pipeline = pipeline.stage(DrCaseStage { eo_axis, dr_axis, cases: self.dr_cases.clone() }, 1);
+        }
+
+        pipeline = match self.htr_subsets.is_empty() {
+            true => pipeline.stage(DrStage { eo_axis, dr_axis }, self.branching.dr),
+            false => pipeline.stage(SubsetDrStage { eo_axis, dr_axis, subsets: self.htr_subsets.clone() }, self.branching.dr)
+        };
+
+        pipeline = pipeline.stage(HtrStage { dr_axis }, self.branching.htr);
+
+        pipeline = match self.leave == Leave::default() {
+            true => pipeline.stage(FinishStage, 1),
+            false => pipeline.stage(LeaveStage::new(dr_axis, self.leave), 1)
+        };
+
+        pipeline
+            .with_niss(self.niss)
+            .with_cancellation(self.cancellation)
+            .with_shared_observer(self.observer.clone())
+            .with_limits(self.limits.clone())
+            .with_metric(self.metric)
+            .with_threads(self.threads)
+    }
+
+    // Runs the pipeline for every combination of EO and DR axes and keeps the shortest,
+    // the first one in axis order among equals
+    pub fn solve(&self, cube: &Cube) -> Option<Solution> {
+        let axes: Vec<(Axis, Axis)> = Axis::ALL
+            .into_iter()
+            .flat_map(|eo_axis| Axis::ALL.into_iter().filter(move |&axis| axis != eo_axis).map(move |dr_axis| (eo_axis, dr_axis)))
+            .collect();
+        let pipeline_threads = Threads { count: (self.threads.count / axes.len()).max(1), ..self.threads };
+
+        let solved = self.threads.map(&axes, |&(eo_axis, dr_axis)| {
+            self.pipeline(eo_axis, dr_axis).with_threads(pipeline_threads).solve(cube)
+        });
+
+        let mut best: Option<Solution> = None;
+
+        for ((eo_axis, dr_axis), steps) in axes.into_iter().zip(solved) {
+            let Some(steps) = steps else {
+                continue;
+            };
+
+            let alg = Pipeline::linear(&steps);
+
+            if best.as_ref().is_none_or(|best| alg.length(self.metric) < best.algorithm.length(self.metric)) {
+                let state = alg.execute(cube.state.normalize());
+
+                best = Some(Solution {
+                    cycles: Cycle::all(&state),
+                    state,
+                    algorithm: alg,
+                    eo_axis,
+                    dr_axis,
+                    steps
+                });
+            }
+        }
 
-        alg
-            .append(&eo_stage)
-            .append(&dr_stage)
-            .append(&ht_stage)
-            .append(&solve_stage);
+        best
+    }
 
-        Some((alg, state))
+    // Every solution of at most `max_length` moves, shortest first. Sequences that pass
+    // through the goal before their last move are skipped, since a prefix of them was
+    // already yielded. Use `take(n)` on the result for the n shortest solutions.
+    pub fn search_all<S: SearchState, G: Fn(S) -> bool, H: Fn(S) -> usize>(
+        state: S,
+        moves: &[Move],
+        goal: G,
+        heuristic: H,
+        max_length: usize
+    ) -> Solutions<S, G, H> {
+        Solutions {
+            start: state,
+            moves: Vec::from(moves),
+            next_length: heuristic(state),
+            goal,
+            heuristic,
+            max_length,
+            length: 0,
+            stack: vec![],
+            alg: Algorithm::new(),
+            progress: Progress::unobserved(),
+            limits: Limits::default(),
+            metric: Metric::default(),
+            exhausted: false,
+            table: TranspositionTable::new(None),
+            found: 0
+        }
+    }
+
+    // IDA* for a single shortest solution in `metric`, in which the heuristic must be
+    // a lower bound
+    pub fn search<S: SearchState + Send>(
+        state: S,
+        moves: &Vec<Move>,
+        goal: impl Fn(S) -> bool + Sync + 'static,
+        heuristic: impl Fn(S) -> usize + Sync + 'static,
+        metric: Metric,
+        limits: &Limits,
+        threads: Threads
+    ) -> SearchOutcome<(Algorithm, S)> {
+        Self::search_with_progress(state, moves, goal, heuristic, metric, limits, threads, &mut Progress::unobserved())
     }
 
-    pub fn search(
-        state: Permutation,
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn search_with_progress<S: SearchState + Send>(
+        state: S,
         moves: &Vec<Move>,
-        goal: impl Fn(Permutation) -> bool + 'static,
-        heuristic: impl Fn(Permutation) -> usize + 'static
-    ) -> Option<(Algorithm, Permutation)> {
+        goal: impl Fn(S) -> bool + Sync + 'static,
+        heuristic: impl Fn(S) -> usize + Sync + 'static,
+        metric: Metric,
+        limits: &Limits,
+        threads: Threads,
+        progress: &mut Progress
+    ) -> SearchOutcome<(Algorithm, S)> {
         let mut alg = Algorithm::new();
         let mut threshold = heuristic(state);
+        let mut table = TranspositionTable::new(limits.transposition_memory);
 
-        loop {
-            match Self::search_inner(state, moves, &goal, &heuristic, &mut alg, 0, threshold) {
-                SearchResult::Success(state) => return Some((alg, state)),
-                SearchResult::Failure => return None,
+        let outcome = loop {
+            if !limits.allows_depth(threshold) {
+                break SearchOutcome::BudgetExhausted;
+            }
+
+            progress.depth(threshold);
+
+            let result = if threads.count > 1 {
+                let (result, found) = Self::search_threads(state, moves, &goal, &heuristic, metric, threshold, limits, threads, progress);
+                alg = found;
+                result
+            } else {
+                Self::search_inner(state, moves, &goal, &heuristic, metric, &mut alg, 0, threshold, limits, &mut table, progress)
+            };
+
+            match result {
+                SearchResult::Success => {
+                    progress.solution(&alg);
+                    let state = alg.moves().iter().fold(state, |state, &mov| state.apply(mov));
+                    break SearchOutcome::Found((alg, state));
+                },
+                SearchResult::Failure => break SearchOutcome::Unsolvable,
+                SearchResult::Exhausted => break SearchOutcome::BudgetExhausted,
                 SearchResult::UpperBound(bound) => threshold = bound
             }
+        };
+
+        progress.finish();
+        outcome
+    }
+
+    // One IDA* iteration with the subtree of each root move searched on whichever thread
+    // is free. Finding a solution cancels the subtrees that can no longer matter: the later
+    // ones when deterministic, all the others otherwise. Node limits apply per thread.
+    #[allow(clippy::too_many_arguments)]
+    fn search_threads<S: SearchState + Send>(
+        state: S,
+        moves: &Vec<Move>,
+        goal: &(impl Fn(S) -> bool + Sync),
+        heuristic: &(impl Fn(S) -> usize + Sync),
+        metric: Metric,
+        threshold: usize,
+        limits: &Limits,
+        threads: Threads,
+        progress: &mut Progress
+    ) -> (SearchResult, Algorithm) {
+        if !limits.allows_nodes(progress.node()) {
+            return (SearchResult::Exhausted, Algorithm::new());
+        }
+
+        let estimate = heuristic(state);
+        if estimate > threshold {
+            return (SearchResult::UpperBound(estimate), Algorithm::new());
+        }
+
+        if goal(state) {
+            return (SearchResult::Success, Algorithm::new());
+        }
+
+        let parent = limits.cancellation.clone().unwrap_or_default();
+        let tokens: Vec<CancellationToken> = moves.iter().map(|_| parent.child()).collect();
+        let (next, results) = (&AtomicUsize::new(0), &Mutex::new(vec![]));
+        let tokens = &tokens;
+
+        thread::scope(|scope| {
+            let workers: Vec<_> = (0..threads.count.min(moves.len()))
+                .map(|_| scope.spawn(move || {
+                    let mut progress = Progress::unobserved();
+                    let mut table = TranspositionTable::new(limits.transposition_memory.map(|bytes| bytes / threads.count));
+
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(&mov) = moves.get(i) else {
+                            break;
+                        };
+
+                        let limits = Limits { cancellation: Some(tokens[i].clone()), ..limits.clone() };
+                        let mut alg = Algorithm::new();
+                        alg.push(mov);
+
+                        let cost = metric.cost(&[], mov);
+                        let result = Self::search_inner(state.apply(mov), moves, goal, heuristic, metric, &mut alg, cost, threshold, &limits, &mut table, &mut progress);
+
+                        if let SearchResult::Success = result {
+                            for (j, token) in tokens.iter().enumerate() {
+                                if j > i || (!threads.deterministic && j != i) {
+                                    token.cancel();
+                                }
+                            }
+                        }
+
+                        results.lock().unwrap().push((i, result, alg));
+                    }
+
+                    progress.nodes()
+                }))
+                .collect();
+
+            for worker in workers {
+                progress.add_nodes(worker.join().unwrap());
+            }
+        });
+
+        let mut results = std::mem::take(&mut *results.lock().unwrap());
+        results.sort_by_key(|&(i, _, _)| i);
+
+        let mut res = SearchResult::Failure;
+        let mut exhausted = false;
+
+        for (_, result, alg) in results {
+            match result {
+                SearchResult::Success => return (SearchResult::Success, alg),
+                // An unfinished subtree might have held an earlier solution
+                SearchResult::Exhausted if threads.deterministic => return (SearchResult::Exhausted, Algorithm::new()),
+                SearchResult::Exhausted => exhausted = true,
+                SearchResult::UpperBound(bound) if match res {
+                    SearchResult::Failure => true,
+                    SearchResult::UpperBound(bound2) if bound < bound2 => true,
+                    _ => false
+                } => res = SearchResult::UpperBound(bound),
+                _ => ()
+            }
+        }
 
-            dbg!(threshold);
+        if exhausted {
+            return (SearchResult::Exhausted, Algorithm::new());
         }
+
+        (res, Algorithm::new())
     }
 
-    fn search_inner(
-        state: Permutation,
+    #[allow(clippy::too_many_arguments)]
+    fn search_inner<S: SearchState>(
+        state: S,
         moves: &Vec<Move>,
-        goal: &impl Fn(Permutation) -> bool,
-        heuristic: &impl Fn(Permutation) -> usize,
+        goal: &impl Fn(S) -> bool,
+        heuristic: &impl Fn(S) -> usize,
+        metric: Metric,
         alg: &mut Algorithm,
         depth: usize,
-        threshold: usize
+        threshold: usize,
+        limits: &Limits,
+        table: &mut TranspositionTable<S>,
+        progress: &mut Progress
     ) -> SearchResult {
+        if !limits.allows_nodes(progress.node()) {
+            return SearchResult::Exhausted;
+        }
+
         let estimate = depth + heuristic(state);
         if estimate > threshold {
             return SearchResult::UpperBound(estimate);
         }
 
         if goal(state) {
-            return SearchResult::Success(state);
+            return SearchResult::Success;
+        }
+
+        // No solution costs up to what was left the last time the node was searched
+        let remaining = threshold - depth;
+        if let Some(searched) = table.get(state, alg.moves()).filter(|&searched| remaining <= searched) {
+            return SearchResult::UpperBound(depth + searched + 1);
         }
 
         let mut res = SearchResult::Failure;
 
         for mov in moves {
-            if let Some(mov2) = alg.last() {
-                if mov.is_similar(mov2) {
-                    continue;
-                }
+            if alg.last().is_some_and(|last| !mov.can_follow(last)) {
+                continue;
             }
 
+            let cost = metric.cost(alg.moves(), *mov);
             alg.push(*mov);
 
-            match Self::search_inner(mov.execute(state), moves, goal, heuristic, alg, depth + 1, threshold) {
-                SearchResult::Success(state) => return SearchResult::Success(state),
+            match Self::search_inner(state.apply(*mov), moves, goal, heuristic, metric, alg, depth + cost, threshold, limits, table, progress) {
+                SearchResult::Success => return SearchResult::Success,
+                SearchResult::Exhausted => return SearchResult::Exhausted,
                 SearchResult::UpperBound(bound) if match res {
                     SearchResult::Failure => true,
                     SearchResult::UpperBound(bound2) if bound < bound2 => true,
@@ -139,12 +571,616 @@ impl Solver {
             alg.pop();
         }
 
+        table.insert(state, alg.moves(), remaining);
         res
     }
+
+    // Every shortest solution from `start` to `goal`, by breadth-first search from both
+    // ends until they meet. Each move counts one, and `moves` must hold the inverse of
+    // each of its moves since the search from the goal undoes them. As in the other
+    // searches, commuting moves only appear in their canonical order.
+    pub fn search_bidirectional<S: SearchState>(start: S, goal: S, moves: &[Move], limits: &Limits) -> SearchOutcome<Vec<Algorithm>> {
+        let progress = &mut Progress::unobserved();
+        debug_assert!(moves.iter().all(|mov| moves.contains(&mov.inverse())));
+
+        let mut trees = [SearchTree::new(start), SearchTree::new(goal)];
+
+        // The first layers to share a state are at the optimal distance, and every
+        // optimal solution goes through one of the states they share
+        let outcome = loop {
+            let meeting: Vec<S> = trees[0].layer.iter().copied().filter(|&state| trees[1].is_in_layer(state)).collect();
+
+            if !meeting.is_empty() {
+                break SearchOutcome::Found(Self::join(&trees, &meeting));
+            }
+
+            if trees.iter().any(|tree| tree.layer.is_empty()) {
+                break SearchOutcome::Unsolvable;
+            }
+
+            let length = trees[0].depth + trees[1].depth + 1;
+            if !limits.allows_depth(length) {
+                break SearchOutcome::BudgetExhausted;
+            }
+
+            progress.depth(length);
+
+            let smaller = usize::from(trees[1].layer.len() < trees[0].layer.len());
+            if !trees[smaller].expand(moves, limits, progress) {
+                break SearchOutcome::BudgetExhausted;
+            }
+        };
+
+        if let SearchOutcome::Found(solutions) = &outcome {
+            for solution in solutions {
+                progress.solution(solution);
+            }
+        }
+
+        progress.finish();
+        outcome
+    }
+
+    // The solutions through each meeting state, the way there from the start followed
+    // by the way on to the goal
+    fn join<S: SearchState>(trees: &[SearchTree<S>; 2], meeting: &[S]) -> Vec<Algorithm> {
+        let mut solutions = vec![];
+
+        for &state in meeting {
+            let (mut firsts, mut seconds) = (vec![], vec![]);
+            trees[0].paths(state, None, true, &mut vec![], &mut firsts);
+            trees[1].paths(state, None, false, &mut vec![], &mut seconds);
+
+            for first in &firsts {
+                for second in &seconds {
+                    if let (Some(&last), Some(&next)) = (first.last(), second.first()) {
+                        if !next.can_follow(last) {
+                            continue;
+                        }
+                    }
+
+                    let mut alg = Algorithm::new();
+                    first.iter().chain(second).for_each(|&mov| alg.push(mov));
+                    solutions.push(alg);
+                }
+            }
+        }
+
+        solutions
+    }
+}
+
+// One side of a bidirectional search: every state reached from the root, with its
+// distance and each (parent, move) it is reached by at that distance
+struct SearchTree<S> {
+    nodes: HashMap<S, (usize, Vec<(S, Move)>)>,
+    layer: Vec<S>,
+    depth: usize,
+}
+
+impl<S: SearchState> SearchTree<S> {
+    fn new(root: S) -> Self {
+        Self {
+            nodes: HashMap::from([(root, (0, vec![]))]),
+            layer: vec![root],
+            depth: 0
+        }
+    }
+
+    fn is_in_layer(&self, state: S) -> bool {
+        self.nodes.get(&state).is_some_and(|&(depth, _)| depth == self.depth)
+    }
+
+    // Reaches the next layer, or returns false if the limits stop it
+    fn expand(&mut self, moves: &[Move], limits: &Limits, progress: &mut Progress) -> bool {
+        let mut next = vec![];
+
+        for &state in &self.layer {
+            if !limits.allows_nodes(progress.node()) {
+                return false;
+            }
+
+            for &mov in moves {
+                let child = state.apply(mov);
+
+                match self.nodes.entry(child) {
+                    Entry::Vacant(entry) => {
+                        entry.insert((self.depth + 1, vec![(state, mov)]));
+                        next.push(child);
+                    },
+                    Entry::Occupied(mut entry) if entry.get().0 == self.depth + 1 => entry.get_mut().1.push((state, mov)),
+                    Entry::Occupied(_) => ()
+                }
+            }
+        }
+
+        self.layer = next;
+        self.depth += 1;
+        true
+    }
+
+    // Every shortest path between the root and `state` in canonical order, added to `out`.
+    // Paths go from the root if `forward` and back to it otherwise, undoing the moves.
+    // `moves` holds the moves from `state` so far, which `last` is the latest of.
+    fn paths(&self, state: S, last: Option<Move>, forward: bool, moves: &mut Vec<Move>, out: &mut Vec<Vec<Move>>) {
+        let (depth, parents) = &self.nodes[&state];
+
+        if *depth == 0 {
+            out.push(match forward {
+                true => moves.iter().rev().copied().collect(),
+                false => moves.iter().map(|mov| mov.inverse()).collect()
+            });
+
+            return;
+        }
+
+        for &(parent, mov) in parents {
+            let ordered = last.is_none_or(|last| if forward { last.can_follow(mov) } else { mov.can_follow(last) });
+
+            if ordered {
+                moves.push(mov);
+                self.paths(parent, Some(mov), forward, moves, out);
+                moves.pop();
+            }
+        }
+    }
+}
+
+pub struct Solutions<S, G, H> {
+    start: S,
+    moves: Vec<Move>,
+    goal: G,
+    heuristic: H,
+    max_length: usize,
+    length: usize,
+    next_length: usize,
+    // Each state being expanded, with the index of its next move, its cost so far and
+    // how many solutions had been found when it was entered
+    stack: Vec<(S, usize, usize, usize)>,
+    alg: Algorithm,
+    progress: Progress,
+    limits: Limits,
+    metric: Metric,
+    exhausted: bool,
+    // Nodes that had no solution at exactly the cost that was left in them. Unlike
+    // IDA*, that says nothing about lower costs, which were enumerated before.
+    table: TranspositionTable<S>,
+    found: usize,
+}
+
+impl<S: SearchState, G: Fn(S) -> bool, H: Fn(S) -> usize> Solutions<S, G, H> {
+    // Reports this search to `observer` as `stage`. The stage finishes once the
+    // iterator runs out or is dropped.
+    pub fn observe(mut self, stage: &'static str, observer: Option<Arc<dyn SolverObserver>>) -> Self {
+        self.progress = Progress::new(stage, observer);
+        self
+    }
+
+    // Stops the iteration early once a limit is reached, see `is_exhausted`
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.table = TranspositionTable::new(limits.transposition_memory);
+        self.limits = limits;
+        self
+    }
+
+    // Counts solution lengths in `metric`. The heuristic must be a lower bound in it.
+    pub fn with_metric(mut self, metric: Metric) -> Self {
+        self.metric = metric;
+        self
+    }
+
+    // Whether the iteration ended because of the limits rather than running out of
+    // solutions, in which case longer solutions may still exist
+    pub fn is_exhausted(&self) -> bool {
+        self.exhausted
+    }
+
+    // Visits a node reached by alg at `cost`, which is either a leaf at the current length
+    // or gets pushed to be expanded. Leaves and pruned nodes undo their move straight away.
+    // In STM a leaf can still go on with a free move that completes a slice.
+    fn enter(&mut self, state: S, cost: usize) -> Option<(Algorithm, S)> {
+        if !self.limits.allows_nodes(self.progress.node()) {
+            self.exhausted = true;
+            return None;
+        }
+
+        if cost > self.length {
+            self.alg.pop();
+            return None;
+        }
+
+        let solved = (self.goal)(state);
+
+        if cost == self.length && (solved || self.metric != Metric::STM) {
+            let solution = solved.then(|| (self.alg.clone(), state));
+            self.found += usize::from(solved);
+            self.alg.pop();
+            return solution;
+        }
+
+        if solved || cost + (self.heuristic)(state) > self.length {
+            self.alg.pop();
+            return None;
+        }
+
+        if self.table.get(state, self.alg.moves()) == Some(self.length - cost) {
+            self.alg.pop();
+            return None;
+        }
+
+        self.stack.push((state, 0, cost, self.found));
+        None
+    }
+}
+
+impl<S: SearchState, G: Fn(S) -> bool, H: Fn(S) -> usize> Iterator for Solutions<S, G, H> {
+    type Item = (Algorithm, S);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.exhausted {
+                self.progress.finish();
+                return None;
+            }
+
+            let Some((state, index, cost, found)) = self.stack.last_mut() else {
+                if self.next_length > self.max_length {
+                    self.progress.finish();
+                    return None;
+                }
+
+                if !self.limits.allows_depth(self.next_length) {
+                    self.exhausted = true;
+                    continue;
+                }
+
+                self.length = self.next_length;
+                self.next_length += 1;
+                self.progress.depth(self.length);
+
+                if let Some(solution) = self.enter(self.start, 0) {
+                    self.progress.solution(&solution.0);
+                    return Some(solution);
+                }
+
+                continue;
+            };
+
+            if *index == self.moves.len() {
+                if *found == self.found {
+                    self.table.insert(*state, self.alg.moves(), self.length - *cost);
+                }
+
+                self.stack.pop();
+                self.alg.pop();
+                continue;
+            }
+
+            let (state, mov, cost) = (*state, self.moves[*index], *cost);
+            *index += 1;
+
+            if self.alg.last().is_some_and(|last| !mov.can_follow(last)) {
+                continue;
+            }
+
+            let cost = cost + self.metric.cost(self.alg.moves(), mov);
+            self.alg.push(mov);
+
+            if let Some(solution) = self.enter(state.apply(mov), cost) {
+                self.progress.solution(&solution.0);
+                return Some(solution);
+            }
+        }
+    }
 }
 
 enum SearchResult {
-    Success(Permutation),
+    Success,
     Failure,
+    Exhausted,
     UpperBound(usize),
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{ Arc, Mutex };
+    use std::time::Duration;
+
+    use super::{ Branching, Solution, Solver, Threads };
+    use crate::algorithm::{ Algorithm, Axis, Move };
+    use crate::analysis::{ DrCase, HtrSubset };
+    use crate::coords::{ DrCoord, EoCoord, HtCoord };
+    use crate::cube::Cube;
+    use crate::leave::{ Cycle, Leave };
+    use crate::limits::{ CancellationToken, Limits };
+    use crate::metric::Metric;
+    use crate::observer::SolverObserver;
+    use crate::permutation::Permutation;
+    use crate::pruning::PruningTables;
+
+    #[test]
+    fn solves_scramble() {
+        let scramble = Algorithm::from_str("R U2 F' L D' B2 R' U F2 D L'").unwrap();
+        let cube = Cube::new().execute(&scramble);
+        let solution = Solver::new().solve(&cube).unwrap();
+        assert_eq!(solution.state, Permutation::ID);
+        assert_eq!(solution.algorithm.execute(cube.state), Permutation::ID);
+        assert_ne!(solution.eo_axis, solution.dr_axis);
+    }
+
+    #[test]
+    fn enumerates_solutions_shortest_first() {
+        let tables = PruningTables::get();
+        let state = Algorithm::from_str("F").unwrap().execute(Permutation::ID);
+        let solutions: Vec<Algorithm> = Solver::search_all(
+            EoCoord::from_perm(&state),
+            &Move::MOVES_LIST,
+            |coord| coord.is_solved(),
+            |coord| tables.eo(coord),
+            3
+        )
+            .map(|(alg, _)| alg)
+            .collect();
+
+        let lengths: Vec<usize> = solutions.iter().map(Algorithm::len).collect();
+        assert!(lengths.is_sorted());
+        assert_eq!(lengths.iter().filter(|&&len| len == 1).count(), 2);
+        assert!(solutions.iter().all(|alg| EoCoord::from_perm(&alg.execute(state)).is_solved()));
+    }
+
+    #[test]
+    fn commuting_moves_are_searched_once() {
+        let state = Algorithm::from_str("U D R L").unwrap().execute(Permutation::ID);
+        let solutions: Vec<String> = Solver::search_all(state, &Move::MOVES_LIST, |perm| perm == Permutation::ID, |_| 0, 4)
+            .map(|(alg, _)| alg.to_string())
+            .collect();
+
+        assert_eq!(solutions, ["L' R' U' D'"]);
+    }
+
+    #[test]
+    fn dr_starts_from_a_preferred_case() {
+        let scramble = Algorithm::from_str("R U2 F' L D' B2 R' U F2 D L'").unwrap();
+        let cube = Cube::new().execute(&scramble);
+        let case = "4c4e".parse::<DrCase>().unwrap();
+        let solution = Solver::new().with_dr_cases(vec![case]).solve(&cube).unwrap();
+
+        assert_eq!(solution.steps.iter().map(|step| step.name).collect::<Vec<_>>(), ["EO", "DR case", "DR", "HT", "Solve"]);
+        assert_eq!(solution.algorithm.execute(cube.state), Permutation::ID);
+
+        let state = solution.steps[1].algorithm.execute(solution.steps[0].algorithm.execute(cube.state));
+        assert!([case, DrCase::SOLVED].contains(&DrCase::of(&state, solution.dr_axis)));
+    }
+
+    #[test]
+    fn prefers_drs_leading_to_chosen_subsets() {
+        let scramble = Algorithm::from_str("R U2 F' L D' B2 R' U F2 D L'").unwrap();
+        let cube = Cube::new().execute(&scramble);
+        let subset = "4a1 4e".parse::<HtrSubset>().unwrap();
+        let solution = Solver::new().with_htr_subsets(vec![subset]).solve(&cube).unwrap();
+        let default = Solver::new().solve(&cube).unwrap();
+        let dr_subset = |solution: &Solution| {
+            let state = solution.steps[1].algorithm.execute(solution.steps[0].algorithm.execute(cube.state));
+            HtrSubset::of(&state, solution.dr_axis)
+        };
+
+        // The shortest DR leaves another subset, so this one takes a longer DR
+        assert_ne!(dr_subset(&default), subset);
+        assert_eq!(dr_subset(&solution), subset);
+        assert!(solution.steps[1].algorithm.len() > default.steps[1].algorithm.len());
+        assert_eq!(solution.algorithm.execute(cube.state), Permutation::ID);
+    }
+
+    #[test]
+    fn stops_at_a_skeleton_within_the_leave() {
+        let scramble = Algorithm::from_str("U' L2 R L D' U R B' F B2 F U' D2 F R F' B R L D'").unwrap();
+        let cube = Cube::new().execute(&scramble);
+        let leave = Leave { corners: 3, edges: 0 };
+        let full = Solver::new().solve(&cube).unwrap();
+        let skeleton = Solver::new().with_leave(leave).solve(&cube).unwrap();
+
+        assert_eq!(skeleton.steps.last().unwrap().name, "Skeleton");
+        assert_eq!(skeleton.algorithm.execute(cube.state), skeleton.state);
+        assert!(leave.allows(&skeleton.state));
+        assert!(skeleton.algorithm.len() < full.algorithm.len());
+        assert!(matches!(skeleton.cycles.as_slice(), [Cycle::Corners(corners, 0)] if corners.len() == 3));
+        assert!(full.cycles.is_empty());
+    }
+
+    #[test]
+    fn branching_never_makes_solutions_longer() {
+        let scramble = Algorithm::from_str("R U2 F' L D' B2 R' U F2 D L'").unwrap();
+        let cube = Cube::new().execute(&scramble);
+        let single = Solver::new().solve(&cube).unwrap();
+        let branched = Solver::new()
+            .with_branching(Branching { eo: 3, dr: 2, htr: 1 })
+            .solve(&cube)
+            .unwrap();
+
+        assert_eq!(branched.state, Permutation::ID);
+        assert!(branched.algorithm.len() <= single.algorithm.len());
+    }
+
+    #[test]
+    fn niss_combines_both_sides() {
+        let scramble = Algorithm::from_str("R U2 F' L D' B2 R' U F2 D L'").unwrap();
+        let cube = Cube::new().execute(&scramble);
+        let solution = Solver::new()
+            .with_branching(Branching { eo: 2, dr: 2, htr: 1 })
+            .with_niss(true)
+            .solve(&cube)
+            .unwrap();
+
+        assert_eq!(solution.state, Permutation::ID);
+        assert_eq!(solution.algorithm.execute(cube.state), Permutation::ID);
+    }
+
+    #[test]
+    fn cancellation_reports_cancelled_length() {
+        let scramble = Algorithm::from_str("R U2 F' L D' B2 R' U F2 D L'").unwrap();
+        let cube = Cube::new().execute(&scramble);
+        let cancelled = Solver::new()
+            .with_branching(Branching { eo: 2, dr: 2, htr: 1 })
+            .with_cancellation(true)
+            .solve(&cube)
+            .unwrap();
+
+        assert_eq!(cancelled.algorithm.execute(cube.state), Permutation::ID);
+        assert_eq!(cancelled.algorithm.len(), cancelled.algorithm.cancel().len());
+    }
+
+    #[derive(Clone, Default)]
+    struct EventLog(Arc<Mutex<Vec<String>>>);
+
+    impl SolverObserver for EventLog {
+        fn stage_started(&self, stage: &str) {
+            self.0.lock().unwrap().push(format!("start {stage}"));
+        }
+
+        fn solution_found(&self, stage: &str, _algorithm: &Algorithm, nodes: u64, _elapsed: Duration) {
+            assert!(nodes > 0);
+            self.0.lock().unwrap().push(format!("solution {stage}"));
+        }
+
+        fn stage_finished(&self, stage: &str, _nodes: u64, _elapsed: Duration) {
+            self.0.lock().unwrap().push(format!("finish {stage}"));
+        }
+    }
+
+    #[test]
+    fn observer_sees_every_stage() {
+        let scramble = Algorithm::from_str("R U2 F' L D' B2 R' U F2 D L'").unwrap();
+        let cube = Cube::new().execute(&scramble);
+        let log = EventLog::default();
+        Solver::new().with_observer(log.clone()).solve(&cube).unwrap();
+
+        let events = log.0.lock().unwrap();
+        for stage in ["EO", "DR", "HT", "Solve"] {
+            let count = |event: &str| events.iter().filter(|e| **e == format!("{event} {stage}")).count();
+            assert_eq!(count("start"), 6);
+            assert_eq!(count("finish"), 6);
+            assert!(count("solution") >= 6);
+        }
+    }
+
+    #[test]
+    fn unreachable_goal_exhausts_the_budget() {
+        let tables = PruningTables::get();
+        let state = EoCoord::from_perm(&Algorithm::from_str("F").unwrap().execute(Permutation::ID));
+        let half_turns: Vec<Move> = Move::MOVES_LIST.into_iter().filter(|mov| mov.is_halfturn()).collect();
+        let limits = Limits { max_nodes: Some(100_000), ..Limits::default() };
+
+        let outcome = Solver::search(state, &half_turns, |coord| coord.is_solved(), |coord| tables.eo(coord), Metric::HTM, &limits, Threads::default());
+        assert!(outcome.is_exhausted());
+    }
+
+    #[test]
+    fn transposition_table_keeps_every_solution() {
+        let tables = PruningTables::get();
+        let state = EoCoord::from_perm(&Algorithm::from_str("R U2 F' L D' B2 R' U F2 D L'").unwrap().execute(Permutation::ID));
+        let limits = Limits { transposition_memory: Some(1 << 20), ..Limits::default() };
+
+        for metric in [Metric::HTM, Metric::STM] {
+            let solutions = |limits: Limits| -> Vec<String> {
+                Solver::search_all(state, &Move::MOVES_LIST, |coord| coord.is_solved(), |coord| metric.from_htm_bound(tables.eo(coord)), 6)
+                    .with_limits(limits)
+                    .with_metric(metric)
+                    .map(|(alg, _)| alg.to_string())
+                    .collect()
+            };
+
+            assert_eq!(solutions(limits.clone()), solutions(Limits::default()));
+        }
+
+        let search = |limits: &Limits| {
+            Solver::search(state, &Vec::from(Move::MOVES_LIST), |coord| coord.is_solved(), |coord| tables.eo(coord).min(1), Metric::HTM, limits, Threads::default())
+                .found()
+                .map(|(alg, _)| alg.to_string())
+        };
+
+        assert_eq!(search(&limits), search(&Limits::default()));
+    }
+
+    #[test]
+    fn bidirectional_search_finds_every_optimal_solution() {
+        let tables = PruningTables::get();
+        let state = HtCoord::from_perm(&Algorithm::from_str("R2 U2 F2 L2 D2 B2 U2 R2 F2").unwrap().execute(Permutation::ID));
+        let half_turns: Vec<Move> = Move::MOVES_LIST.into_iter().filter(|mov| mov.is_halfturn()).collect();
+
+        let optimal = Solver::search_bidirectional(state, HtCoord::SOLVED, &half_turns, &Limits::default()).found().unwrap();
+        let mut both_ends: Vec<String> = optimal.iter().map(Algorithm::to_string).collect();
+        let mut forward: Vec<String> = Solver::search_all(state, &half_turns, |coord| coord.is_solved(), |coord| tables.finish(coord), 20)
+            .map(|(alg, _)| alg)
+            .take_while(|alg| alg.len() == optimal[0].len())
+            .map(|alg| alg.to_string())
+            .collect();
+
+        both_ends.sort();
+        forward.sort();
+        assert_eq!(both_ends, forward);
+    }
+
+    #[test]
+    fn cancelled_search_stops() {
+        let tables = PruningTables::get();
+        let state = EoCoord::from_perm(&Algorithm::from_str("F").unwrap().execute(Permutation::ID));
+        let cancellation = CancellationToken::new();
+        let limits = Limits { cancellation: Some(cancellation.clone()), ..Limits::default() };
+        let mut solutions = Solver::search_all(state, &Move::MOVES_LIST, |coord| coord.is_solved(), |coord| tables.eo(coord), 20)
+            .with_limits(limits);
+
+        assert!(solutions.next().is_some());
+        cancellation.cancel();
+        assert!(solutions.next().is_none());
+        assert!(solutions.is_exhausted());
+    }
+
+    #[test]
+    fn threads_find_the_same_first_solution() {
+        let tables = PruningTables::get();
+        let scramble = Algorithm::from_str("R U2 F' L D' B2 R' U").unwrap();
+        let state = DrCoord::from_perm(&scramble.execute(Permutation::ID));
+        let moves = Vec::from(Move::MOVES_LIST);
+        let search = |threads| {
+            Solver::search(state, &moves, |coord| coord.is_solved(), |coord| tables.dr(coord).min(1), Metric::HTM, &Limits::default(), threads)
+                .found()
+                .unwrap()
+                .0
+                .to_string()
+        };
+
+        let single = search(Threads::default());
+        assert_eq!(search(Threads { count: 4, deterministic: true }), single);
+
+        let any = Solver::search(state, &moves, |coord| coord.is_solved(), |coord| tables.dr(coord).min(1), Metric::HTM, &Limits::default(), Threads { count: 4, deterministic: false })
+            .found()
+            .unwrap();
+        assert_eq!(any.0.len(), single.split_whitespace().count());
+        assert!(any.1.is_solved());
+    }
+
+    #[test]
+    fn solver_threads_find_the_same_solution() {
+        let scramble = Algorithm::from_str("R U2 F' L D' B2 R' U F2 D L'").unwrap();
+        let cube = Cube::new().execute(&scramble);
+        let solver = || Solver::new().with_branching(Branching { eo: 3, dr: 2, htr: 1 });
+        let single = solver().solve(&cube).unwrap();
+        let threaded = solver().with_threads(Threads { count: 12, deterministic: true }).solve(&cube).unwrap();
+
+        assert_eq!(threaded.algorithm.to_string(), single.algorithm.to_string());
+        assert_eq!((threaded.eo_axis, threaded.dr_axis), (single.eo_axis, single.dr_axis));
+    }
+
+    #[test]
+    fn slice_moves_count_one_in_stm() {
+        let state = Algorithm::from_str("M' U2 M").unwrap().execute(Permutation::ID);
+        let moves: Vec<Move> = Move::MOVES_LIST.into_iter().chain(Move::SLICE_MOVES).filter(|mov| mov.axis() != Axis::FB).collect();
+        let (solution, _) = Solver::search_all(state, &moves, |perm| perm == Permutation::ID, |_| 0, 3)
+            .with_metric(Metric::STM)
+            .next()
+            .unwrap();
+
+        assert_eq!(solution.length(Metric::STM), 3);
+        assert_eq!(solution.execute(state), Permutation::ID);
+    }
+}
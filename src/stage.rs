@@ -0,0 +1,454 @@
+use std::sync::Arc;
+
+use crate::algorithm::{ Algorithm, Axis, Move };
+use crate::analysis::{ DrCase, HtrSubset };
+use crate::coords::{ DrCoord, EoCoord, HtCoord, HtrCoord };
+use crate::halfturn::HalfTurnTable;
+use crate::kociemba::{ Phase2Coord, Phase2Tables };
+use crate::leave::Leave;
+use crate::limits::Limits;
+use crate::metric::Metric;
+use crate::observer::{ Progress, SolverObserver };
+use crate::permutation::Permutation;
+use crate::pruning::PruningTables;
+use crate::solver::{ SearchState, Solver };
+
+// One step of a method: a goal reached with a given set of moves. Only the name, the
+// moves and the goal are required; the default search runs on whole permutations with
+// `heuristic` as its lower bound, which is fine for short stages. Stages that need to
+// be fast override `solutions` and search on coordinates, as the built-in ones do.
+pub trait Stage: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn moves(&self) -> Vec<Move>;
+
+    fn is_solved(&self, perm: &Permutation) -> bool;
+
+    // Must never overestimate the number of face turns left (HTM). Searches in other
+    // metrics convert it.
+    fn heuristic(&self, _perm: &Permutation) -> usize {
+        0
+    }
+
+    // Whether the stage may be solved on the inverse scramble when NISS is enabled
+    fn allows_niss(&self) -> bool {
+        true
+    }
+
+    // Every solution from `state`, shortest first
+    fn solutions<'a>(&'a self, state: Permutation, search: &StageSearch) -> Box<dyn Iterator<Item = Algorithm> + 'a> {
+        search.run(
+            self.name(),
+            state,
+            &self.moves(),
+            move |perm| self.is_solved(&perm),
+            move |perm| self.heuristic(&perm)
+        )
+    }
+}
+
+// What a pipeline hands to each stage search: the longest solution worth looking for,
+// the limits, the metric and who to report progress to
+pub struct StageSearch {
+    pub max_length: usize,
+    limits: Limits,
+    observer: Option<Arc<dyn SolverObserver>>,
+    metric: Metric,
+}
+
+impl StageSearch {
+    pub(crate) fn new(max_length: usize, limits: Limits, observer: Option<Arc<dyn SolverObserver>>, metric: Metric) -> Self {
+        Self { max_length, limits, observer, metric }
+    }
+
+    // IDA* from `start`, reported as `stage` and bounded by the pipeline's limits.
+    // `heuristic` counts face turns like Stage::heuristic.
+    pub fn run<'a, S: SearchState + 'a>(
+        &self,
+        stage: &'static str,
+        start: S,
+        moves: &[Move],
+        goal: impl Fn(S) -> bool + 'a,
+        heuristic: impl Fn(S) -> usize + 'a
+    ) -> Box<dyn Iterator<Item = Algorithm> + 'a> {
+        let metric = self.metric;
+
+        Box::new(
+            Solver::search_all(start, moves, goal, move |state| metric.from_htm_bound(heuristic(state)), self.max_length)
+                .observe(stage, self.observer.clone())
+                .with_limits(self.limits.clone())
+                .with_metric(metric)
+                .map(|(alg, _)| alg)
+        )
+    }
+
+    // The same search allowing solutions of up to `max_length`, for stages that are
+    // longer than most
+    pub(crate) fn with_max_length(&self, max_length: usize) -> Self {
+        Self::new(max_length, self.limits.clone(), self.observer.clone(), self.metric)
+    }
+
+    // Reports a stage that isn't found by searching
+    pub(crate) fn progress(&self, stage: &'static str) -> Progress {
+        Progress::new(stage, self.observer.clone())
+    }
+
+    // Like `run`, from a state seen with the cube rotated by `rotation`. The moves are
+    // given in that rotated frame and the solutions are turned back into the original one.
+    pub(crate) fn run_rotated<'a, S: SearchState + 'a>(
+        &self,
+        stage: &'static str,
+        start: S,
+        rotation: Permutation,
+        moves: &[Move],
+        goal: impl Fn(S) -> bool + 'a,
+        heuristic: impl Fn(S) -> usize + 'a
+    ) -> Box<dyn Iterator<Item = Algorithm> + 'a> {
+        let solutions = self.run(stage, start, moves, goal, heuristic);
+        Box::new(solutions.map(move |alg| alg.rotate(-rotation)))
+    }
+}
+
+// Edge orientation relative to `axis`, with any move
+pub struct EoStage {
+    pub axis: Axis,
+}
+
+impl Stage for EoStage {
+    fn name(&self) -> &'static str {
+        "EO"
+    }
+
+    fn moves(&self) -> Vec<Move> {
+        Vec::from(Move::MOVES_LIST)
+    }
+
+    fn is_solved(&self, perm: &Permutation) -> bool {
+        EoCoord::from_perm(&perm.conjugate(self.rotation())).is_solved()
+    }
+
+    fn heuristic(&self, perm: &Permutation) -> usize {
+        PruningTables::get().eo(EoCoord::from_perm(&perm.conjugate(self.rotation())))
+    }
+
+    fn solutions<'a>(&'a self, state: Permutation, search: &StageSearch) -> Box<dyn Iterator<Item = Algorithm> + 'a> {
+        let tables = PruningTables::get();
+
+        search.run_rotated(
+            self.name(),
+            EoCoord::from_perm(&state.conjugate(self.rotation())),
+            self.rotation(),
+            &Move::MOVES_LIST,
+            |coord| coord.is_solved(),
+            |coord| tables.eo(coord)
+        )
+    }
+}
+
+impl EoStage {
+    fn rotation(&self) -> Permutation {
+        rotation_to(&[(self.axis, Axis::FB)])
+    }
+}
+
+// Keeps EO on `eo_axis` and sets up one of `cases` for DR on `dr_axis`, so that DR is
+// finished from a case of choice rather than from wherever EO left the cube. DR itself
+// always counts as one of the cases.
+pub struct DrCaseStage {
+    pub eo_axis: Axis,
+    pub dr_axis: Axis,
+    pub cases: Vec<DrCase>,
+}
+
+impl Stage for DrCaseStage {
+    fn name(&self) -> &'static str {
+        "DR case"
+    }
+
+    fn moves(&self) -> Vec<Move> {
+        rotated_moves(self.rotation(), Move::keeps_eo)
+    }
+
+    fn is_solved(&self, perm: &Permutation) -> bool {
+        self.is_case(DrCoord::from_perm(&perm.conjugate(self.rotation())))
+    }
+
+    fn solutions<'a>(&'a self, state: Permutation, search: &StageSearch) -> Box<dyn Iterator<Item = Algorithm> + 'a> {
+        let moves: Vec<Move> = Move::MOVES_LIST.into_iter().filter(|mov| mov.keeps_eo()).collect();
+
+        search.run_rotated(
+            self.name(),
+            DrCoord::from_perm(&state.conjugate(self.rotation())),
+            self.rotation(),
+            &moves,
+            |coord| self.is_case(coord),
+            |_| 0
+        )
+    }
+}
+
+impl DrCaseStage {
+    fn rotation(&self) -> Permutation {
+        rotation_to(&[(self.eo_axis, Axis::FB), (self.dr_axis, Axis::UD)])
+    }
+
+    fn is_case(&self, coord: DrCoord) -> bool {
+        coord.is_solved() || self.cases.contains(&DrCase::from_coord(coord))
+    }
+}
+
+// Domino reduction on `dr_axis` from EO on `eo_axis`, keeping the edges oriented
+pub struct DrStage {
+    pub eo_axis: Axis,
+    pub dr_axis: Axis,
+}
+
+impl Stage for DrStage {
+    fn name(&self) -> &'static str {
+        "DR"
+    }
+
+    fn moves(&self) -> Vec<Move> {
+        rotated_moves(self.rotation(), Move::keeps_eo)
+    }
+
+    fn is_solved(&self, perm: &Permutation) -> bool {
+        DrCoord::from_perm(&perm.conjugate(self.rotation())).is_solved()
+    }
+
+    fn heuristic(&self, perm: &Permutation) -> usize {
+        PruningTables::get().dr(DrCoord::from_perm(&perm.conjugate(self.rotation())))
+    }
+
+    fn solutions<'a>(&'a self, state: Permutation, search: &StageSearch) -> Box<dyn Iterator<Item = Algorithm> + 'a> {
+        let tables = PruningTables::get();
+        let moves: Vec<Move> = Move::MOVES_LIST.into_iter().filter(|mov| mov.keeps_eo()).collect();
+
+        search.run_rotated(
+            self.name(),
+            DrCoord::from_perm(&state.conjugate(self.rotation())),
+            self.rotation(),
+            &moves,
+            |coord| coord.is_solved(),
+            |coord| tables.dr(coord)
+        )
+    }
+}
+
+impl DrStage {
+    fn rotation(&self) -> Permutation {
+        rotation_to(&[(self.eo_axis, Axis::FB), (self.dr_axis, Axis::UD)])
+    }
+}
+
+// How many DR solutions SubsetDrStage sorts by subset
+const SUBSET_CANDIDATES: usize = 64;
+
+// DR like DrStage, trying first the solutions that leave one of `subsets` for HTR.
+// Solutions up to one move longer than the shortest are considered, so a slightly
+// longer DR wins if it leads to an easier HTR.
+pub struct SubsetDrStage {
+    pub eo_axis: Axis,
+    pub dr_axis: Axis,
+    pub subsets: Vec<HtrSubset>,
+}
+
+impl Stage for SubsetDrStage {
+    fn name(&self) -> &'static str {
+        "DR"
+    }
+
+    fn moves(&self) -> Vec<Move> {
+        self.dr().moves()
+    }
+
+    fn is_solved(&self, perm: &Permutation) -> bool {
+        self.dr().is_solved(perm)
+    }
+
+    fn heuristic(&self, perm: &Permutation) -> usize {
+        self.dr().heuristic(perm)
+    }
+
+    fn solutions<'a>(&'a self, state: Permutation, search: &StageSearch) -> Box<dyn Iterator<Item = Algorithm> + 'a> {
+        let mut shortest = None;
+        let mut solutions: Vec<Algorithm> = self.dr()
+            .solutions(state, search)
+            .take_while(|alg| alg.length(search.metric) <= *shortest.get_or_insert(alg.length(search.metric)) + 1)
+            .take(SUBSET_CANDIDATES)
+            .collect();
+
+        solutions.sort_by_key(|alg| !self.subsets.contains(&HtrSubset::of(&alg.execute(state), self.dr_axis)));
+        Box::new(solutions.into_iter())
+    }
+}
+
+impl SubsetDrStage {
+    fn dr(&self) -> DrStage {
+        DrStage { eo_axis: self.eo_axis, dr_axis: self.dr_axis }
+    }
+}
+
+// Half-turn reduction from DR on `dr_axis`, with domino moves
+pub struct HtrStage {
+    pub dr_axis: Axis,
+}
+
+impl Stage for HtrStage {
+    fn name(&self) -> &'static str {
+        "HT"
+    }
+
+    fn moves(&self) -> Vec<Move> {
+        rotated_moves(self.rotation(), Move::keeps_domino)
+    }
+
+    fn is_solved(&self, perm: &Permutation) -> bool {
+        HtrCoord::from_perm(&perm.conjugate(self.rotation())).is_solved()
+    }
+
+    fn heuristic(&self, perm: &Permutation) -> usize {
+        PruningTables::get().htr(HtrCoord::from_perm(&perm.conjugate(self.rotation())))
+    }
+
+    fn solutions<'a>(&'a self, state: Permutation, search: &StageSearch) -> Box<dyn Iterator<Item = Algorithm> + 'a> {
+        let tables = PruningTables::get();
+        let moves: Vec<Move> = Move::MOVES_LIST.into_iter().filter(|mov| mov.keeps_domino()).collect();
+
+        search.run_rotated(
+            self.name(),
+            HtrCoord::from_perm(&state.conjugate(self.rotation())),
+            self.rotation(),
+            &moves,
+            |coord| coord.is_solved(),
+            |coord| tables.htr(coord)
+        )
+    }
+}
+
+impl HtrStage {
+    fn rotation(&self) -> Permutation {
+        rotation_to(&[(self.dr_axis, Axis::UD)])
+    }
+}
+
+// Solves a half-turn reduced cube with half turns only, always on the normal scramble
+pub struct FinishStage;
+
+impl Stage for FinishStage {
+    fn name(&self) -> &'static str {
+        "Solve"
+    }
+
+    fn moves(&self) -> Vec<Move> {
+        Move::MOVES_LIST.into_iter().filter(|mov| mov.is_halfturn()).collect()
+    }
+
+    fn is_solved(&self, perm: &Permutation) -> bool {
+        *perm == Permutation::ID
+    }
+
+    fn heuristic(&self, perm: &Permutation) -> usize {
+        PruningTables::get().finish(HtCoord::from_perm(perm))
+    }
+
+    fn allows_niss(&self) -> bool {
+        false
+    }
+
+    // Half turns all cost the same, so the optimal finishes are read off the complete
+    // distance table, except in STM where two of them can pair up into a slice
+    fn solutions<'a>(&'a self, state: Permutation, search: &StageSearch) -> Box<dyn Iterator<Item = Algorithm> + 'a> {
+        let table = HalfTurnTable::get();
+        let coord = HtCoord::from_perm(&state);
+
+        if search.metric != Metric::STM || table.distance(coord).is_none() {
+            let solutions = table.solutions_with_progress(coord, &mut search.progress(self.name()));
+            return Box::new(solutions.into_iter());
+        }
+
+        search.run(
+            self.name(),
+            coord,
+            &self.moves(),
+            |coord| coord.is_solved(),
+            |coord| table.distance(coord).unwrap()
+        )
+    }
+}
+
+// Finishes from HTR on `dr_axis` with domino moves but stops as soon as no more pieces
+// are unsolved than `leave` allows, giving a skeleton to insert into. Quarter turns of
+// the DR layers reach skeletons that half turns alone can't, like a corner 3-cycle.
+pub struct LeaveStage {
+    pub dr_axis: Axis,
+    pub leave: Leave,
+    tables: Arc<Phase2Tables>,
+}
+
+impl LeaveStage {
+    pub fn new(dr_axis: Axis, leave: Leave) -> Self {
+        Self { dr_axis, leave, tables: Phase2Tables::leaving(leave) }
+    }
+
+    fn rotation(&self) -> Permutation {
+        rotation_to(&[(self.dr_axis, Axis::UD)])
+    }
+}
+
+impl Stage for LeaveStage {
+    fn name(&self) -> &'static str {
+        "Skeleton"
+    }
+
+    fn moves(&self) -> Vec<Move> {
+        rotated_moves(self.rotation(), Move::keeps_domino)
+    }
+
+    fn is_solved(&self, perm: &Permutation) -> bool {
+        self.leave.allows(perm)
+    }
+
+    fn heuristic(&self, perm: &Permutation) -> usize {
+        self.tables.distance(Phase2Coord::from_perm(&perm.conjugate(self.rotation())))
+    }
+
+    fn allows_niss(&self) -> bool {
+        false
+    }
+
+    fn solutions<'a>(&'a self, state: Permutation, search: &StageSearch) -> Box<dyn Iterator<Item = Algorithm> + 'a> {
+        let moves: Vec<Move> = Move::MOVES_LIST.into_iter().filter(|mov| mov.keeps_domino()).collect();
+
+        search.run_rotated(
+            self.name(),
+            Phase2Coord::from_perm(&state.conjugate(self.rotation())),
+            self.rotation(),
+            &moves,
+            |coord| self.tables.distance(coord) == 0,
+            |coord| self.tables.distance(coord)
+        )
+    }
+}
+
+// A rotation taking each `from` axis to its `to` axis
+pub(crate) fn rotation_to(axes: &[(Axis, Axis)]) -> Permutation {
+    Permutation::rotations()
+        .into_iter()
+        .find(|&rotation| axes.iter().all(|&(from, to)| rotate_axis(from, rotation) == to))
+        .unwrap()
+}
+
+fn rotate_axis(axis: Axis, rotation: Permutation) -> Axis {
+    Move::MOVES_LIST
+        .into_iter()
+        .find(|mov| mov.axis() == axis)
+        .unwrap()
+        .rotate(rotation)
+        .axis()
+}
+
+// The moves that satisfy `keep` once the cube is rotated by `rotation`
+fn rotated_moves(rotation: Permutation, keep: impl Fn(Move) -> bool) -> Vec<Move> {
+    Move::MOVES_LIST.into_iter().filter(|mov| keep(mov.rotate(rotation))).collect()
+}
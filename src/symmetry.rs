@@ -0,0 +1,127 @@
+use crate::algorithm::Algorithm;
+use crate::permutation::Permutation;
+
+// Where each location goes when the cube is mirrored left to right
+const MIRROR_CORNERS: [usize; 8] = [1, 0, 3, 2, 5, 4, 7, 6];
+const MIRROR_EDGES: [usize; 12] = [2, 1, 0, 3, 7, 6, 5, 4, 10, 9, 8, 11];
+const MIRROR_CENTERS: [usize; 6] = [0, 1, 3, 2, 4, 5];
+
+// One of the 48 symmetries of the cube: a left-right mirror if `mirrored`, followed by
+// one of the 24 rotations. Mirrors aren't states of the cube, but conjugating by one
+// still turns a state into a state, the one reached by mirroring every move.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Symmetry {
+    pub rotation: Permutation,
+    pub mirrored: bool,
+}
+
+impl Symmetry {
+    pub const ID: Self = Self { rotation: Permutation::ID, mirrored: false };
+
+    // All 48 symmetries, starting with the identity
+    pub fn all() -> Vec<Self> {
+        [false, true]
+            .into_iter()
+            .flat_map(|mirrored| {
+                Permutation::rotations()
+                    .into_iter()
+                    .map(move |rotation| Self { rotation, mirrored })
+            })
+            .collect()
+    }
+
+    // The same state seen through this symmetry: S⋅P⋅S⁻¹ in the usual notation, which
+    // for a rotation is `perm.conjugate(rotation)`. A state and its conjugate are the
+    // same case, solved by algorithms transformed the same way.
+    pub fn conjugate(self, perm: Permutation) -> Permutation {
+        let perm = if self.mirrored { mirror(perm) } else { perm };
+        perm.conjugate(self.rotation)
+    }
+
+    // The algorithm with the same effect on conjugated states
+    pub fn transform(self, alg: &Algorithm) -> Algorithm {
+        let alg = if self.mirrored { alg.mirror() } else { alg.clone() };
+        alg.rotate(self.rotation)
+    }
+
+    // The smallest of the 48 conjugates of `perm`, with the symmetry giving it. Two
+    // states are the same case exactly when their representatives are equal.
+    pub fn canonical(perm: Permutation) -> (Permutation, Symmetry) {
+        Self::all()
+            .into_iter()
+            .map(|symmetry| (symmetry.conjugate(perm), symmetry))
+            .min_by_key(|&(perm, _)| perm)
+            .unwrap()
+    }
+}
+
+fn mirror(perm: Permutation) -> Permutation {
+    let mut new = perm;
+
+    for i in 0..8 {
+        new.cp[i] = MIRROR_CORNERS[perm.cp[MIRROR_CORNERS[i]]];
+        new.co[i] = (3 - perm.co[MIRROR_CORNERS[i]]) % 3;
+    }
+
+    for i in 0..12 {
+        new.ep[i] = MIRROR_EDGES[perm.ep[MIRROR_EDGES[i]]];
+        new.eo[i] = perm.eo[MIRROR_EDGES[i]];
+    }
+
+    for i in 0..6 {
+        new.centers[i] = MIRROR_CENTERS[perm.centers[MIRROR_CENTERS[i]]];
+    }
+
+    new
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ mirror, Symmetry };
+    use crate::algorithm::{ Algorithm, Move };
+    use crate::permutation::Permutation;
+
+    #[test]
+    fn there_are_48_symmetries() {
+        let all = Symmetry::all();
+        let states: Vec<Permutation> = all
+            .iter()
+            .map(|symmetry| symmetry.conjugate(Algorithm::from_str("R U2 F'").unwrap().execute(Permutation::ID)))
+            .collect();
+
+        assert_eq!(all.len(), 48);
+        assert!(states.iter().enumerate().all(|(i, state)| !states[..i].contains(state)));
+    }
+
+    #[test]
+    fn mirroring_matches_mirrored_moves() {
+        for mov in Move::MOVES_LIST.into_iter().chain(Move::SLICE_MOVES).chain(Move::ROTATIONS) {
+            let alg = Algorithm::from_str(&mov.to_string()).unwrap();
+            assert_eq!(mirror(alg.execute(Permutation::ID)), alg.mirror().execute(Permutation::ID));
+        }
+
+        assert_eq!(Algorithm::from_str("R U F2").unwrap().mirror().to_string(), "L' U' F2");
+    }
+
+    #[test]
+    fn transformed_algorithms_solve_conjugates() {
+        let scramble = Algorithm::from_str("R U2 F' L D' B2 R' U F2 D L'").unwrap();
+        let state = scramble.execute(Permutation::ID);
+
+        for symmetry in Symmetry::all() {
+            assert_eq!(symmetry.transform(&scramble).execute(Permutation::ID), symmetry.conjugate(state));
+        }
+    }
+
+    #[test]
+    fn symmetric_cases_share_a_representative() {
+        let sune = Algorithm::from_str("R U R' U R U2 R'").unwrap().execute(Permutation::ID);
+        let left_sune = Algorithm::from_str("L' U' L U' L' U2 L").unwrap().execute(Permutation::ID);
+        let back_sune = Algorithm::from_str("L U L' U L U2 L'").unwrap().execute(Permutation::ID);
+        let t_perm = Algorithm::from_str("R U R' U' R' F R2 U' R' U' R U R' F'").unwrap().execute(Permutation::ID);
+
+        assert_eq!(Symmetry::canonical(sune).0, Symmetry::canonical(left_sune).0);
+        assert_eq!(Symmetry::canonical(sune).0, Symmetry::canonical(back_sune).0);
+        assert_ne!(Symmetry::canonical(sune).0, Symmetry::canonical(t_perm).0);
+    }
+}
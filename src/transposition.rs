@@ -0,0 +1,87 @@
+use std::hash::{ DefaultHasher, Hash, Hasher };
+use std::mem;
+
+use crate::algorithm::Move;
+use crate::solver::SearchState;
+
+// A node is its state together with the last two moves, since those decide which moves
+// may come next and what they cost
+type Key<S> = (S, [Option<Move>; 2]);
+
+#[derive(Clone, Copy)]
+struct Entry<S> {
+    key: Key<S>,
+    remaining: usize,
+}
+
+// Nodes whose subtree was searched without finding a solution, with the cost that was
+// left to spend in it. The table has a fixed number of slots and a node overwrites
+// whatever was in its slot, so it never uses more memory than it was created with.
+pub(crate) struct TranspositionTable<S> {
+    entries: Vec<Option<Entry<S>>>,
+}
+
+impl<S: SearchState> TranspositionTable<S> {
+    // A table that fits in `bytes`, or one that remembers nothing without them
+    pub(crate) fn new(bytes: Option<usize>) -> Self {
+        let len = bytes.map_or(0, |bytes| bytes / mem::size_of::<Option<Entry<S>>>());
+        Self { entries: vec![None; len] }
+    }
+
+    // The remaining cost the node reached by `previous` was last searched with, if any
+    pub(crate) fn get(&self, state: S, previous: &[Move]) -> Option<usize> {
+        let key = Self::key(state, previous);
+
+        match self.entries.get(self.slot(&key))? {
+            Some(entry) if entry.key == key => Some(entry.remaining),
+            _ => None
+        }
+    }
+
+    pub(crate) fn insert(&mut self, state: S, previous: &[Move], remaining: usize) {
+        let key = Self::key(state, previous);
+        let slot = self.slot(&key);
+
+        if let Some(entry) = self.entries.get_mut(slot) {
+            *entry = Some(Entry { key, remaining });
+        }
+    }
+
+    fn key(state: S, previous: &[Move]) -> Key<S> {
+        let mut last = previous.iter().rev().copied();
+        (state, [last.next(), last.next()])
+    }
+
+    fn slot(&self, key: &Key<S>) -> usize {
+        if self.entries.is_empty() {
+            return 0;
+        }
+
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        (hasher.finish() % self.entries.len() as u64) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TranspositionTable;
+    use crate::algorithm::Algorithm;
+    use crate::permutation::Permutation;
+
+    #[test]
+    fn remembers_nodes_within_its_memory() {
+        let alg = Algorithm::from_str("R U F").unwrap();
+        let state = alg.execute(Permutation::ID);
+        let mut table = TranspositionTable::new(Some(1 << 16));
+        table.insert(state, alg.moves(), 5);
+
+        assert_eq!(table.get(state, alg.moves()), Some(5));
+        assert_eq!(table.get(state, &alg.moves()[..2]), None);
+        assert!(table.entries.len() * size_of::<Option<super::Entry<Permutation>>>() <= 1 << 16);
+
+        let mut disabled = TranspositionTable::new(None);
+        disabled.insert(state, alg.moves(), 5);
+        assert_eq!(disabled.get(state, alg.moves()), None);
+    }
+}
@@ -0,0 +1,467 @@
+use std::sync::{ Arc, OnceLock };
+
+use crate::algorithm::{ Algorithm, Move };
+use crate::cfop::{ Face, OcllStage, PllStage };
+use crate::coords::{ EoCoord, MoveTables, PieceMoves, EO_SIZE, PIECE_STATES };
+use crate::cube::Cube;
+use crate::limits::Limits;
+use crate::metric::Metric;
+use crate::observer::SolverObserver;
+use crate::optimal::{ OptimalState, OptimalTables };
+use crate::permutation::{ EdgeLoc, Permutation };
+use crate::pipeline::Pipeline;
+use crate::pruning::distance_table;
+use crate::roux::{ Block, BlockState, BLOCK_SIZE, FIRST_BLOCK, SECOND_BLOCK };
+use crate::solver::{ SearchState, Step };
+use crate::stage::{ Stage, StageSearch };
+
+const EO_EDGES_SIZE: usize = EO_SIZE * PIECE_STATES * PIECE_STATES;
+
+// The edges of the line, and the other two D edges that make it a cross
+const LINE_EDGES: [usize; 2] = [EdgeLoc::DF as usize, EdgeLoc::DB as usize];
+const SIDE_EDGES: [usize; 2] = [EdgeLoc::DL as usize, EdgeLoc::DR as usize];
+
+// How ZZ starts: orienting the edges along with the DF and DB edges, or with all four D
+// edges
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ZzStart {
+    #[default]
+    EoLine,
+    EoCross,
+}
+
+// How ZZ finishes once F2L is done: OCLL then PLL from the standard algorithms, as the
+// edges are already oriented, or the whole last layer in one optimal ZBLL
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ZzLastLayer {
+    #[default]
+    OcllPll,
+    Zbll,
+}
+
+// Edge orientation relative to F/B with where each D edge is, see PieceMoves
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+struct EoEdgesState {
+    eo: u16,
+    line: [u8; 2],
+    sides: [u8; 2],
+}
+
+impl EoEdgesState {
+    fn from_perm(perm: &Permutation) -> Self {
+        Self {
+            eo: EoCoord::from_perm(perm).0,
+            line: LINE_EDGES.map(|piece| PieceMoves::edge_state(perm, piece)),
+            sides: SIDE_EDGES.map(|piece| PieceMoves::edge_state(perm, piece))
+        }
+    }
+}
+
+impl SearchState for EoEdgesState {
+    fn apply(self, mov: Move) -> Self {
+        let pieces = PieceMoves::get();
+
+        Self {
+            eo: MoveTables::get().eo.apply(self.eo, mov),
+            line: self.line.map(|edge| pieces.edge(edge, mov)),
+            sides: self.sides.map(|edge| pieces.edge(edge, mov))
+        }
+    }
+}
+
+fn eo_edges_index(eo: u16, [first, second]: [u8; 2]) -> usize {
+    (eo as usize * PIECE_STATES + first as usize) * PIECE_STATES + second as usize
+}
+
+// Distances in each stage's own moves: EO with either pair of D edges with any face
+// turn, and each block with R, U and L
+struct ZzTables {
+    eo_line: Vec<u8>,
+    eo_sides: Vec<u8>,
+    left_block: Vec<u8>,
+    right_block: Vec<u8>,
+}
+
+impl ZzTables {
+    fn get() -> &'static Self {
+        static TABLES: OnceLock<ZzTables> = OnceLock::new();
+        TABLES.get_or_init(Self::generate)
+    }
+
+    fn generate() -> Self {
+        let tables = MoveTables::get();
+        let pieces = PieceMoves::get();
+        let eo_edges = |edges: [usize; 2]| {
+            let solved = eo_edges_index(0, edges.map(|piece| PieceMoves::edge_state(&Permutation::ID, piece)));
+
+            distance_table(EO_EDGES_SIZE, &[solved], &Move::MOVES_LIST, |i, mov| {
+                let eo = tables.eo.apply((i / (PIECE_STATES * PIECE_STATES)) as u16, mov);
+                let edges = [i / PIECE_STATES % PIECE_STATES, i % PIECE_STATES].map(|edge| pieces.edge(edge as u8, mov));
+                eo_edges_index(eo, edges)
+            })
+        };
+        let block = |block: &Block| {
+            distance_table(BLOCK_SIZE, &[BlockState::solved(block).index()], &f2l_moves(), |i, mov| {
+                BlockState::from_index(i).apply(mov).index()
+            })
+        };
+
+        Self {
+            eo_line: eo_edges(LINE_EDGES),
+            eo_sides: eo_edges(SIDE_EDGES),
+            left_block: block(&FIRST_BLOCK),
+            right_block: block(&SECOND_BLOCK)
+        }
+    }
+
+    fn eo_line(&self, state: EoEdgesState) -> usize {
+        self.eo_line[eo_edges_index(state.eo, state.line)] as usize
+    }
+
+    fn eo_cross(&self, state: EoEdgesState) -> usize {
+        self.eo_line(state).max(self.eo_sides[eo_edges_index(state.eo, state.sides)] as usize)
+    }
+
+    fn f2l(&self, (left, right): (BlockState, BlockState)) -> usize {
+        self.left_block[left.index()].max(self.right_block[right.index()]) as usize
+    }
+}
+
+// R, U and L keep the edges oriented and the line in place
+fn f2l_moves() -> Vec<Move> {
+    ["R", "R2", "R'", "U", "U2", "U'", "L", "L2", "L'"].map(|mov| Move::from_str(mov).unwrap()).into()
+}
+
+// Orients every edge relative to F/B and solves DF and DB, with any face turn
+pub struct EoLineStage;
+
+impl Stage for EoLineStage {
+    fn name(&self) -> &'static str {
+        "EOLine"
+    }
+
+    fn moves(&self) -> Vec<Move> {
+        Vec::from(Move::MOVES_LIST)
+    }
+
+    fn is_solved(&self, perm: &Permutation) -> bool {
+        self.heuristic(perm) == 0
+    }
+
+    fn heuristic(&self, perm: &Permutation) -> usize {
+        ZzTables::get().eo_line(EoEdgesState::from_perm(perm))
+    }
+
+    fn allows_niss(&self) -> bool {
+        false
+    }
+
+    fn solutions<'a>(&'a self, state: Permutation, search: &StageSearch) -> Box<dyn Iterator<Item = Algorithm> + 'a> {
+        let tables = ZzTables::get();
+
+        search.run(
+            self.name(),
+            EoEdgesState::from_perm(&state),
+            &self.moves(),
+            |state| tables.eo_line(state) == 0,
+            |state| tables.eo_line(state)
+        )
+    }
+}
+
+// Orients every edge relative to F/B and solves all four D edges, with any face turn
+pub struct EoCrossStage;
+
+impl Stage for EoCrossStage {
+    fn name(&self) -> &'static str {
+        "EOCross"
+    }
+
+    fn moves(&self) -> Vec<Move> {
+        Vec::from(Move::MOVES_LIST)
+    }
+
+    fn is_solved(&self, perm: &Permutation) -> bool {
+        self.heuristic(perm) == 0
+    }
+
+    fn heuristic(&self, perm: &Permutation) -> usize {
+        ZzTables::get().eo_cross(EoEdgesState::from_perm(perm))
+    }
+
+    fn allows_niss(&self) -> bool {
+        false
+    }
+
+    fn solutions<'a>(&'a self, state: Permutation, search: &StageSearch) -> Box<dyn Iterator<Item = Algorithm> + 'a> {
+        let tables = ZzTables::get();
+
+        search.run(
+            self.name(),
+            EoEdgesState::from_perm(&state),
+            &self.moves(),
+            |state| tables.eo_cross(state) == 0,
+            |state| tables.eo_cross(state)
+        )
+    }
+}
+
+// The 1x2x3 block on the left, with R, U and L
+pub struct LeftBlockStage;
+
+impl Stage for LeftBlockStage {
+    fn name(&self) -> &'static str {
+        "Left block"
+    }
+
+    fn moves(&self) -> Vec<Move> {
+        f2l_moves()
+    }
+
+    fn is_solved(&self, perm: &Permutation) -> bool {
+        BlockState::of(perm, &FIRST_BLOCK) == BlockState::solved(&FIRST_BLOCK)
+    }
+
+    fn heuristic(&self, perm: &Permutation) -> usize {
+        ZzTables::get().left_block[BlockState::of(perm, &FIRST_BLOCK).index()] as usize
+    }
+
+    fn allows_niss(&self) -> bool {
+        false
+    }
+
+    fn solutions<'a>(&'a self, state: Permutation, search: &StageSearch) -> Box<dyn Iterator<Item = Algorithm> + 'a> {
+        let tables = ZzTables::get();
+        let solved = BlockState::solved(&FIRST_BLOCK);
+
+        search.run(
+            self.name(),
+            BlockState::of(&state, &FIRST_BLOCK),
+            &self.moves(),
+            move |block| block == solved,
+            |block| tables.left_block[block.index()] as usize
+        )
+    }
+}
+
+// The 1x2x3 block on the right, with R, U and L as long as the left one is back in place
+// at the end
+pub struct RightBlockStage;
+
+impl Stage for RightBlockStage {
+    fn name(&self) -> &'static str {
+        "Right block"
+    }
+
+    fn moves(&self) -> Vec<Move> {
+        f2l_moves()
+    }
+
+    fn is_solved(&self, perm: &Permutation) -> bool {
+        self.heuristic(perm) == 0
+    }
+
+    fn heuristic(&self, perm: &Permutation) -> usize {
+        ZzTables::get().f2l((BlockState::of(perm, &FIRST_BLOCK), BlockState::of(perm, &SECOND_BLOCK)))
+    }
+
+    fn allows_niss(&self) -> bool {
+        false
+    }
+
+    fn solutions<'a>(&'a self, state: Permutation, search: &StageSearch) -> Box<dyn Iterator<Item = Algorithm> + 'a> {
+        let tables = ZzTables::get();
+
+        search.run(
+            self.name(),
+            (BlockState::of(&state, &FIRST_BLOCK), BlockState::of(&state, &SECOND_BLOCK)),
+            &self.moves(),
+            |blocks| tables.f2l(blocks) == 0,
+            |blocks| tables.f2l(blocks)
+        )
+    }
+}
+
+// The whole last layer at once with any face turn, searched optimally with the tables of
+// the optimal solver rather than picked from an algorithm set
+pub struct ZbllStage;
+
+impl Stage for ZbllStage {
+    fn name(&self) -> &'static str {
+        "ZBLL"
+    }
+
+    fn moves(&self) -> Vec<Move> {
+        Vec::from(Move::MOVES_LIST)
+    }
+
+    fn is_solved(&self, perm: &Permutation) -> bool {
+        *perm == Permutation::ID
+    }
+
+    fn heuristic(&self, perm: &Permutation) -> usize {
+        OptimalTables::get().lower_bound(OptimalState::from_perm(perm))
+    }
+
+    fn allows_niss(&self) -> bool {
+        false
+    }
+
+    fn solutions<'a>(&'a self, state: Permutation, search: &StageSearch) -> Box<dyn Iterator<Item = Algorithm> + 'a> {
+        let tables = OptimalTables::get();
+        let solved = OptimalState::from_perm(&Permutation::ID);
+
+        search.run(
+            self.name(),
+            OptimalState::from_perm(&state),
+            &self.moves(),
+            move |state| state == solved,
+            |state| tables.lower_bound(state)
+        )
+    }
+}
+
+pub struct ZzSolution {
+    pub algorithm: Algorithm,
+    // EOLine or EOCross, the left and right blocks, then OCLL and PLL or ZBLL
+    pub steps: Vec<Step>,
+}
+
+// Solves with the ZZ method, each stage optimally with its own moves: EOLine or EOCross
+// with any face turn, F2L as a block on each side with R, U and L, which keep the edges
+// oriented, and the last layer with any face turn. The line is on D with the last layer
+// on U.
+pub struct ZzSolver {
+    start: ZzStart,
+    last_layer: ZzLastLayer,
+    observer: Option<Arc<dyn SolverObserver>>,
+    limits: Limits,
+    metric: Metric,
+}
+
+impl Default for ZzSolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ZzSolver {
+    pub fn new() -> Self {
+        Self {
+            start: ZzStart::default(),
+            last_layer: ZzLastLayer::default(),
+            observer: None,
+            limits: Limits::default(),
+            metric: Metric::default()
+        }
+    }
+
+    pub fn with_start(mut self, start: ZzStart) -> Self {
+        self.start = start;
+        self
+    }
+
+    pub fn with_last_layer(mut self, last_layer: ZzLastLayer) -> Self {
+        self.last_layer = last_layer;
+        self
+    }
+
+    pub fn with_observer(mut self, observer: impl SolverObserver + 'static) -> Self {
+        self.observer = Some(Arc::new(observer));
+        self
+    }
+
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn with_metric(mut self, metric: Metric) -> Self {
+        self.metric = metric;
+        self
+    }
+
+    pub fn pipeline(&self) -> Pipeline {
+        let pipeline = match self.start {
+            ZzStart::EoLine => Pipeline::new().stage(EoLineStage, 1),
+            ZzStart::EoCross => Pipeline::new().stage(EoCrossStage, 1)
+        };
+        let pipeline = pipeline.stage(LeftBlockStage, 1).stage(RightBlockStage, 1);
+        let pipeline = match self.last_layer {
+            ZzLastLayer::OcllPll => pipeline.stage(OcllStage { face: Face::D }, 1).stage(PllStage { face: Face::D }, 1),
+            ZzLastLayer::Zbll => pipeline.stage(ZbllStage, 1)
+        };
+
+        pipeline
+            .with_shared_observer(self.observer.clone())
+            .with_limits(self.limits.clone())
+            .with_metric(self.metric)
+    }
+
+    pub fn solve(&self, cube: &Cube) -> Option<ZzSolution> {
+        let steps = self.pipeline().solve(cube)?;
+        Some(ZzSolution { algorithm: Pipeline::linear(&steps), steps })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ EoCrossStage, EoLineStage, ZbllStage, ZzLastLayer, ZzSolver, ZzStart };
+    use crate::algorithm::Algorithm;
+    use crate::coords::EoCoord;
+    use crate::cube::Cube;
+    use crate::limits::Limits;
+    use crate::metric::Metric;
+    use crate::permutation::Permutation;
+    use crate::stage::{ Stage, StageSearch };
+
+    #[test]
+    fn solves_with_zz() {
+        let scramble = Algorithm::from_str("R U2 F' L D' B2 R' U F2 D L' B U2 R2 F D2 L B' U").unwrap();
+        let cube = Cube::new().execute(&scramble);
+        let solution = ZzSolver::new().solve(&cube).unwrap();
+        let after = |steps: usize| solution.steps[..steps].iter().fold(cube.state.normalize(), |state, step| step.algorithm.execute(state));
+
+        assert_eq!(
+            solution.steps.iter().map(|step| step.name).collect::<Vec<_>>(),
+            ["EOLine", "Left block", "Right block", "OCLL", "PLL"]
+        );
+        assert_eq!(solution.algorithm.execute(cube.state), Permutation::ID);
+        assert!(EoLineStage.is_solved(&after(1)));
+        assert!(solution.steps[1..3].iter().flat_map(|step| step.algorithm.moves()).all(|mov| {
+            ["R", "U", "L"].contains(&&mov.to_string()[..1])
+        }));
+        assert!(EoCoord::from_perm(&after(3)).is_solved());
+    }
+
+    #[test]
+    fn solves_eo_cross() {
+        let cube = Cube::new().execute(&Algorithm::from_str("F R D2 L'").unwrap());
+        let search = StageSearch::new(20, Limits::default(), None, Metric::default());
+        let eo_cross = EoCrossStage.solutions(cube.state, &search).next().unwrap();
+        let eo_line = EoLineStage.solutions(cube.state, &search).next().unwrap();
+
+        assert_eq!(eo_cross.len(), 4);
+        assert!(eo_line.len() <= 4);
+        assert!(EoCrossStage.is_solved(&eo_cross.execute(cube.state)));
+
+        let solution = ZzSolver::new().with_start(ZzStart::EoCross).solve(&cube).unwrap();
+        assert_eq!(solution.steps[0].name, "EOCross");
+        assert_eq!(solution.algorithm.execute(cube.state), Permutation::ID);
+    }
+
+    #[test]
+    fn solves_zbll_optimally() {
+        let state = Algorithm::from_str("R U R' U R U2 R' U").unwrap().execute(Permutation::ID);
+        let search = StageSearch::new(20, Limits::default(), None, Metric::default());
+        let zbll = ZbllStage.solutions(state, &search).next().unwrap();
+
+        assert_eq!(zbll.len(), 8);
+        assert_eq!(zbll.execute(state), Permutation::ID);
+
+        let cube = Cube::new().execute(&Algorithm::from_str("R U R' U' R' F R2 U' R' U' R U R' F' U").unwrap());
+        let solution = ZzSolver::new().with_last_layer(ZzLastLayer::Zbll).solve(&cube).unwrap();
+        assert_eq!(solution.steps.last().unwrap().name, "ZBLL");
+        assert_eq!(solution.algorithm.execute(cube.state), Permutation::ID);
+    }
+}